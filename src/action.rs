@@ -2,6 +2,7 @@
 
 use crate::{
     state::{Page, StatusMessage},
+    util::Persisted,
     value::Value,
 };
 
@@ -44,6 +45,36 @@ pub enum Action {
     ///
     NavBottom,
 
+    /// Follows a `$ref` into `path` of the referenced `file`, `None` for the edited document.
+    NavFollow {
+        ///
+        file: Option<String>,
+
+        ///
+        path: String,
+    },
+
+    /// Keeps a file loaded to follow `$ref`s into it.
+    ExternalLoad {
+        /// Absolute path of the file.
+        file: String,
+
+        ///
+        doc: Value,
+    },
+
+    /// Goes back to the previous location in the jump list.
+    JumpBack,
+
+    /// Goes forward to the next location in the jump list.
+    JumpForward,
+
+    /// Choose `path` as the result of pick mode.
+    Pick {
+        ///
+        path: String,
+    },
+
     ///
     DocumentReplaceCurrent {
         ///
@@ -86,6 +117,93 @@ pub enum Action {
         value: String,
     },
 
+    ///
+    SaveAsPromptSetValue {
+        ///
+        value: String,
+    },
+
+    /// Allow saving over an existing file once it was warned about.
+    ConfirmOverwrite {
+        ///
+        file_name: String,
+    },
+
+    ///
+    CommandLineSetValue {
+        ///
+        value: String,
+    },
+
+    /// Remembers a line run from the command line.
+    CommandLineRecord {
+        ///
+        line: String,
+    },
+
+    /// Shows the line run before the one in the command line.
+    CommandLineHistoryUp,
+
+    /// Shows the line run after the one in the command line.
+    CommandLineHistoryDown,
+
+    /// Marks `path` with the letter `mark`, replacing what it marked before.
+    SetMark {
+        ///
+        mark: char,
+
+        ///
+        path: String,
+    },
+
+    /// Deletes the mark selected in the list of marks.
+    DeleteMark,
+
+    ///
+    MarksUp,
+
+    ///
+    MarksDown,
+
+    /// Looks up the `$ref`s pointing at `path` or anything below it.
+    FindUsages {
+        ///
+        path: String,
+    },
+
+    ///
+    UsagesUp,
+
+    ///
+    UsagesDown,
+
+    ///
+    ProblemsUp,
+
+    ///
+    ProblemsDown,
+
+    /// Sorts the entries of the object or array at the current location.
+    SortCurrent,
+
+    ///
+    SetReadOnly {
+        ///
+        read_only: bool,
+    },
+
+    ///
+    SetFile {
+        ///
+        file_name: String,
+
+        ///
+        persisted: Persisted,
+    },
+
+    /// Stop warning that saving drops the comments of the file, and save it without them.
+    DiscardComments,
+
     ///
     SetStatus {
         ///
@@ -95,3 +213,18 @@ pub enum Action {
         timeout: Option<core::time::Duration>,
     },
 }
+
+impl Action {
+    /// Whether the action changes the document, which read only mode refuses.
+    pub const fn mutates_document(&self) -> bool {
+        matches!(
+            self,
+            &Self::DocumentReplaceCurrent { .. }
+                | &Self::NavMoveUp
+                | &Self::NavMoveDown
+                | &Self::Undo
+                | &Self::Redo
+                | &Self::SortCurrent
+        )
+    }
+}
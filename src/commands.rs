@@ -0,0 +1,575 @@
+//! Subcommands that work on a document without starting the interface, so
+//! rover can be used from scripts.
+
+use std::{
+    io::{self, Write as _},
+    process::ExitCode,
+};
+
+use anyhow::{anyhow, bail, Context as _};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use similar::TextDiff;
+
+use crate::{
+    config::Config,
+    format::Format,
+    pointer::{ValuePointer, Walk},
+    search::{index, ranked, PatternCache},
+    util::{
+        drops_comments, fetch_document, parse_document, read_input, render_doc, save_doc, STDIN,
+    },
+    value::Value,
+};
+
+/// Exit code when a pointer doesn't resolve to a value in the document, or a
+/// search has no results.
+pub const NOT_FOUND: u8 = 1;
+
+/// Exit code when the document can't be read or the result can't be written.
+pub const FAILURE: u8 = 2;
+
+///
+pub fn subcommands() -> [Command; 5] {
+    [
+        Command::new("get")
+            .about("Print the value at a JSON pointer")
+            .after_help("Exits with 1 when the pointer doesn't resolve and 2 on any other error.")
+            .arg(file_arg())
+            .arg(pointer_arg())
+            .arg(output_arg()),
+        Command::new("search")
+            .about("Print the pointers matching a query, best match first")
+            .after_help("Exits with 1 when nothing matches and 2 on any other error.")
+            .arg(file_arg())
+            .arg(
+                Arg::new("query")
+                    .required(true)
+                    .help("Query in the syntax of the search view, like `operationId$ *'list`"),
+            )
+            .arg(
+                Arg::new("scores")
+                    .long("scores")
+                    .action(ArgAction::SetTrue)
+                    .help("Print the score of every match before its pointer"),
+            )
+            .arg(
+                Arg::new("values")
+                    .long("values")
+                    .action(ArgAction::SetTrue)
+                    .help("Print the value of every match after its pointer, as compact JSON"),
+            )
+            .arg(
+                Arg::new("json")
+                    .long("json")
+                    .action(ArgAction::SetTrue)
+                    .help("Print the matches as a JSON array"),
+            )
+            .arg(
+                Arg::new("limit")
+                    .long("limit")
+                    .short('n')
+                    .value_parser(clap::value_parser!(usize))
+                    .help("Print at most this many matches"),
+            ),
+        Command::new("paths")
+            .about("Print the pointer of every value in the document, in document order")
+            .arg(file_arg())
+            .arg(
+                Arg::new("leaves")
+                    .long("leaves")
+                    .action(ArgAction::SetTrue)
+                    .help("Only print values with nothing below them"),
+            )
+            .arg(
+                Arg::new("types")
+                    .long("types")
+                    .action(ArgAction::SetTrue)
+                    .help("Print the type of every value after its pointer"),
+            )
+            .arg(
+                Arg::new("values")
+                    .long("values")
+                    .action(ArgAction::SetTrue)
+                    .help("Print every value after its pointer, as compact JSON"),
+            )
+            .arg(
+                Arg::new("max-depth")
+                    .long("max-depth")
+                    .short('d')
+                    .value_parser(clap::value_parser!(usize))
+                    .help("Don't descend further than this many keys below the root"),
+            ),
+        edit_command("set")
+            .about("Replace or add the value at a JSON pointer and save the document")
+            .after_help(format!(
+                "The value is read as YAML, so JSON works too: `rover set openapi.yaml \
+                 '#/info/version' '\"2.0.0\"'`. Use `-` as the last key to append to a list.\n\n\
+                 {EDIT_EXIT_CODES}"
+            ))
+            .arg(
+                Arg::new("value")
+                    .required_unless_present("from")
+                    .conflicts_with("from")
+                    .help("New value, as a JSON or YAML literal"),
+            )
+            .arg(
+                Arg::new("from")
+                    .long("from")
+                    .help("Read the new value from a document instead, `-` for standard input"),
+            ),
+        edit_command("delete")
+            .about("Remove the value at a JSON pointer and save the document")
+            .after_help(EDIT_EXIT_CODES),
+    ]
+}
+
+///
+const EDIT_EXIT_CODES: &str = "Exits with 1 when the pointer doesn't resolve and 2 on any other \
+                               error, like comments that can't be kept without `--drop-comments`.";
+
+/// A subcommand that changes the value at a pointer and saves the document.
+fn edit_command(name: &'static str) -> Command {
+    Command::new(name)
+        .arg(file_arg())
+        .arg(pointer_arg())
+        .arg(dry_run_arg())
+        .arg(drop_comments_arg())
+}
+
+/// Prints `value` to standard output in `format`.
+pub fn write_value(value: &Value, format: Format) -> anyhow::Result<()> {
+    print(&terminated(format.encode(value)?, format))
+}
+
+/// Runs the subcommand `name`, reporting any error on standard error.
+pub fn run(name: &str, matches: &ArgMatches) -> ExitCode {
+    let result = match name {
+        "get" => get(matches),
+        "search" => search(matches),
+        "paths" => paths(matches),
+        "set" => set(matches),
+        "delete" => edit(matches, |pointer, doc| pointer.remove(doc).map(|_| ())),
+        _ => Err(anyhow!("Unknown command: {name}")),
+    };
+
+    result.unwrap_or_else(|err| {
+        report(&format!("{err:#}"));
+        ExitCode::from(FAILURE)
+    })
+}
+
+///
+fn file_arg() -> Arg {
+    Arg::new("file")
+        .required(true)
+        .help("Document to read, `-` for standard input")
+}
+
+///
+fn pointer_arg() -> Arg {
+    Arg::new("pointer")
+        .required(true)
+        .help("JSON pointer of the value, like `/components/schemas`")
+}
+
+///
+fn dry_run_arg() -> Arg {
+    Arg::new("dry-run")
+        .long("dry-run")
+        .action(ArgAction::SetTrue)
+        .help("Print a diff of the change instead of saving it")
+}
+
+///
+fn drop_comments_arg() -> Arg {
+    Arg::new("drop-comments")
+        .long("drop-comments")
+        .action(ArgAction::SetTrue)
+        .help("Save even if the comments of the document can't be kept")
+}
+
+///
+fn output_arg() -> Arg {
+    Arg::new("output")
+        .long("output")
+        .short('o')
+        .help("Format to print in, the format of the document by default")
+        .value_parser(|format: &str| format.parse::<Format>())
+}
+
+///
+fn get(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let file_name = required(matches, "file")?;
+    let pointer = required(matches, "pointer")?;
+    let format = matches.get_one::<Format>("format").copied();
+
+    let (doc, persisted) = fetch_document(file_name, format)?;
+    let value = match pointer.parse::<ValuePointer>()?.get(&doc) {
+        Ok(value) => value,
+        Err(err) => {
+            report(&format!("No value at {pointer}: {err}"));
+            return Ok(ExitCode::from(NOT_FOUND));
+        }
+    };
+
+    let output = matches
+        .get_one::<Format>("output")
+        .copied()
+        .unwrap_or_else(|| persisted.format.fragment());
+
+    write_value(value, output)?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+///
+fn search(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let file_name = required(matches, "file")?;
+    let query = required(matches, "query")?;
+    let format = matches.get_one::<Format>("format").copied();
+    let limit = matches
+        .get_one::<usize>("limit")
+        .copied()
+        .unwrap_or(usize::MAX);
+
+    let (doc, _) = fetch_document(file_name, format)?;
+
+    let paths = index(&doc);
+    let weights = Config::load()?.search.fuzzy;
+    let mut cache = PatternCache::new(1).weights(weights);
+    let mut deref_cache = PatternCache::new(1).weights(weights);
+    cache.reset(paths.clone());
+    deref_cache.reset(paths);
+
+    let results = ranked(&doc, &mut cache, &mut deref_cache, query)?
+        .into_iter()
+        .take(limit)
+        .map(|(path, score)| {
+            let value = if matches.get_flag("values") {
+                let value = path.parse::<ValuePointer>()?.get(&doc)?;
+                Some(serde_json::to_value(value)?)
+            } else {
+                None
+            };
+            Ok((path, score, value))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let output = if matches.get_flag("json") {
+        let entries = results
+            .iter()
+            .map(|&(ref path, score, ref value)| {
+                let mut entry = serde_json::Map::new();
+                entry.insert("pointer".to_owned(), path.clone().into());
+                entry.insert("score".to_owned(), score.into());
+                if let Some(value) = value.clone() {
+                    entry.insert("value".to_owned(), value);
+                }
+                serde_json::Value::Object(entry)
+            })
+            .collect::<Vec<_>>();
+        format!("{}\n", serde_json::to_string_pretty(&entries)?)
+    } else {
+        results
+            .iter()
+            .map(|&(ref path, score, ref value)| {
+                let mut fields = Vec::new();
+                if matches.get_flag("scores") {
+                    fields.push(score.to_string());
+                }
+                fields.push(path.clone());
+                if let Some(value) = value.as_ref() {
+                    fields.push(value.to_string());
+                }
+                let mut line = fields.join("\t");
+                line.push('\n');
+                line
+            })
+            .collect()
+    };
+    print(output.as_bytes())?;
+
+    Ok(if results.is_empty() {
+        ExitCode::from(NOT_FOUND)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+///
+fn paths(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let file_name = required(matches, "file")?;
+    let format = matches.get_one::<Format>("format").copied();
+
+    let (doc, _) = fetch_document(file_name, format)?;
+
+    let mut walk = Walk::new(&doc);
+    if let Some(&depth) = matches.get_one::<usize>("max-depth") {
+        walk = walk.max_depth(depth);
+    }
+
+    let mut output = String::new();
+    for (path, value, _) in walk {
+        if matches.get_flag("leaves") && !value.is_leaf() {
+            continue;
+        }
+
+        let mut fields = vec![path];
+        if matches.get_flag("types") {
+            fields.push(value.type_name().to_owned());
+        }
+        if matches.get_flag("values") {
+            fields.push(serde_json::to_string(value)?);
+        }
+
+        output.push_str(&fields.join("\t"));
+        output.push('\n');
+    }
+    print(output.as_bytes())?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+///
+fn set(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let value = match matches.get_one::<String>("from") {
+        Some(from) if from == STDIN && required(matches, "file")? == STDIN => {
+            bail!("Only one of the document and the value can be read from standard input")
+        }
+        Some(from) => fetch_document(from, None)?.0,
+        None => {
+            serde_yaml::from_str::<Value>(required(matches, "value")?).context("Invalid value")?
+        }
+    };
+
+    edit(matches, |pointer, doc| pointer.set(doc, value).map(|_| ()))
+}
+
+/// Applies `change` to the value at the pointer and saves the document, or
+/// prints what would change for a dry run. A document read from standard
+/// input is written to standard output instead.
+fn edit<F>(matches: &ArgMatches, change: F) -> anyhow::Result<ExitCode>
+where
+    F: FnOnce(&ValuePointer, &mut Value) -> anyhow::Result<()>,
+{
+    let file_name = required(matches, "file")?;
+    let pointer = required(matches, "pointer")?;
+    let format = matches.get_one::<Format>("format").copied();
+
+    let data = read_input(file_name)?;
+    let (doc, persisted) = parse_document(file_name, &data, format)?;
+    let source = persisted.source.as_ref();
+
+    let mut edited = doc.clone();
+    if let Err(err) = change(&pointer.parse::<ValuePointer>()?, &mut edited) {
+        report(&format!("No value at {pointer}: {err}"));
+        return Ok(ExitCode::from(NOT_FOUND));
+    }
+
+    // Where the editor asks twice, a script has to opt in to losing the comments
+    let drops = drops_comments(&edited, &persisted, persisted.format);
+    if drops && !matches.get_flag("drop-comments") {
+        report(&if file_name == STDIN {
+            "The comments of the document can't be kept, pass --drop-comments to write it anyway"
+                .to_owned()
+        } else {
+            format!("Saving drops the comments in {file_name}, pass --drop-comments to save anyway")
+        });
+        return Ok(ExitCode::from(FAILURE));
+    }
+    let kept = source.filter(|_| !drops);
+
+    if matches.get_flag("dry-run") {
+        // The file as it is against the bytes that would be written, binary formats are
+        // compared as JSON
+        let (before, after) = if persisted.format.is_binary() {
+            let fragment = persisted.format.fragment();
+            (fragment.serialize(&doc)?, fragment.serialize(&edited)?)
+        } else {
+            let original = match persisted.compression {
+                Some(compression) => compression.decompress(&data)?,
+                None => data,
+            };
+            let rendered = render_doc(&edited, kept, persisted.format, &persisted.datetimes)?;
+            (String::from_utf8(original)?, String::from_utf8(rendered)?)
+        };
+        let diff = TextDiff::from_lines(&before, &after)
+            .unified_diff()
+            .header(file_name, file_name)
+            .to_string();
+
+        print(diff.as_bytes())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if file_name == STDIN {
+        print(&render_doc(
+            &edited,
+            kept,
+            persisted.format,
+            &persisted.datetimes,
+        )?)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    save_doc(
+        file_name,
+        &edited,
+        persisted.format,
+        persisted.compression,
+        kept,
+        &persisted.datetimes,
+        Some(persisted.fingerprint),
+        Config::load()?.backup(),
+    )?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+///
+fn required<'matches>(matches: &'matches ArgMatches, name: &str) -> anyhow::Result<&'matches str> {
+    matches
+        .get_one::<String>(name)
+        .map(String::as_str)
+        .ok_or_else(|| anyhow!("Missing {name} in argument list."))
+}
+
+/// Text output ends with a newline, like any other command line tool's.
+fn terminated(mut data: Vec<u8>, format: Format) -> Vec<u8> {
+    if !format.is_binary() && !data.ends_with(b"\n") {
+        data.push(b'\n');
+    }
+    data
+}
+
+///
+fn print(data: &[u8]) -> anyhow::Result<()> {
+    io::stdout()
+        .write_all(data)
+        .context("Unable to write to standard output")
+}
+
+///
+fn report(message: &str) {
+    if writeln!(io::stderr(), "rover: {message}").is_err() {
+        log::error!("{message}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process};
+
+    use anyhow::bail;
+
+    use super::*;
+
+    #[test]
+    fn test_get_exit_codes() -> anyhow::Result<()> {
+        let path = env::temp_dir().join(format!("rover-commands-{}.yaml", process::id()));
+        fs::write(&path, "a:\n  b: 1\n")?;
+        let file_name = path.to_string_lossy();
+        let missing = format!("{file_name}.missing");
+
+        let cases = [
+            (file_name.as_ref(), "/a/b", ExitCode::SUCCESS),
+            (file_name.as_ref(), "/a/c", ExitCode::from(NOT_FOUND)),
+            (missing.as_str(), "/a/b", ExitCode::from(FAILURE)),
+        ];
+
+        for (file_name, pointer, expected) in cases {
+            let matches =
+                crate::command().try_get_matches_from(["rover", "get", file_name, pointer])?;
+            let Some((name, matches)) = matches.subcommand() else {
+                bail!("Expected a subcommand");
+            };
+
+            let code = run(name, matches);
+            if code != expected {
+                bail!("Expected {:?} for {:?}, got {:?}", expected, pointer, code);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_and_delete() -> anyhow::Result<()> {
+        let path = env::temp_dir().join(format!("rover-commands-edit-{}.yaml", process::id()));
+        fs::write(
+            &path,
+            "info:\n  version: 1.0.0 # current\npaths:\n  /legacy: {}\n  /pets: {}\n",
+        )?;
+        let file_name = path.to_string_lossy();
+
+        let edits = [
+            vec!["rover", "set", &file_name, "#/info/version", "\"2.0.0\""],
+            vec!["rover", "delete", &file_name, "#/paths/~1legacy"],
+            vec!["rover", "delete", &file_name, "#/paths/~1pets", "--dry-run"],
+        ];
+
+        for args in edits {
+            let matches = crate::command().try_get_matches_from(&args)?;
+            let Some((name, matches)) = matches.subcommand() else {
+                bail!("Expected a subcommand");
+            };
+
+            let code = run(name, matches);
+            if code != ExitCode::SUCCESS {
+                bail!("Expected {:?} to succeed, got {:?}", args, code);
+            }
+        }
+
+        let contents = fs::read_to_string(&path)?;
+        let expected = "info:\n  version: 2.0.0 # current\npaths:\n  /pets: {}\n";
+        if contents != expected {
+            bail!("Expected {:?}, got {:?}", expected, contents);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_refuses_to_drop_comments() -> anyhow::Result<()> {
+        let path = env::temp_dir().join(format!("rover-commands-comments-{}.toml", process::id()));
+        let original = "# cargo\n[package]\nversion = \"1.0.0\"\n";
+        fs::write(&path, original)?;
+        let file_name = path.to_string_lossy();
+
+        let cases = [
+            (vec![], ExitCode::from(FAILURE), original),
+            (
+                vec!["--drop-comments"],
+                ExitCode::SUCCESS,
+                "[package]\nversion = \"2.0.0\"\n",
+            ),
+        ];
+
+        for (flags, expected_code, expected) in cases {
+            let mut args = vec!["rover", "set", &file_name, "#/package/version", "2.0.0"];
+            args.extend(flags);
+            let matches = crate::command().try_get_matches_from(&args)?;
+            let Some((name, matches)) = matches.subcommand() else {
+                bail!("Expected a subcommand");
+            };
+
+            let code = run(name, matches);
+            if code != expected_code {
+                bail!(
+                    "Expected {:?} for {:?}, got {:?}",
+                    expected_code,
+                    args,
+                    code
+                );
+            }
+
+            let contents = fs::read_to_string(&path)?;
+            if contents != expected {
+                bail!("Expected {:?}, got {:?}", expected, contents);
+            }
+        }
+
+        Ok(())
+    }
+}
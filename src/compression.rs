@@ -0,0 +1,161 @@
+//! Compression documents can be wrapped in, applied on top of their [`Format`](crate::format::Format)
+//! so `spec.json.gz` is still read as JSON.
+
+use std::{
+    io::{Read as _, Write as _},
+    path::Path,
+};
+
+use anyhow::Context as _;
+use flate2::{read::GzDecoder, write::GzEncoder};
+
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    ///
+    Gzip,
+
+    ///
+    Zstd,
+}
+
+impl Compression {
+    /// Compression named by the last extension of `path`.
+    pub fn from_path<P: AsRef<Path> + ?Sized>(path: &P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+
+        match extension.to_ascii_lowercase().as_str() {
+            "gz" | "gzip" => Some(Self::Gzip),
+            "zst" | "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Compression `data` is wrapped in, going by its magic bytes.
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::Gzip)
+        } else if data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Compression of the file in `path`, falling back to its contents when the
+    /// file name doesn't tell.
+    pub fn detect<P: AsRef<Path> + ?Sized>(path: &P, data: &[u8]) -> Option<Self> {
+        Self::from_path(path).or_else(|| Self::sniff(data))
+    }
+
+    ///
+    pub fn decompress(self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        match self {
+            Self::Gzip => {
+                GzDecoder::new(data)
+                    .read_to_end(&mut decompressed)
+                    .context("Unable to decompress gzip data")?;
+            }
+            Self::Zstd => {
+                decompressed = zstd::decode_all(data).context("Unable to decompress zstd data")?;
+            }
+        }
+
+        Ok(decompressed)
+    }
+
+    ///
+    pub fn compress(self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            Self::Zstd => Ok(zstd::encode_all(data, 0)?),
+        }
+    }
+
+    /// `file_name` without the extension of its compression, if it has one.
+    pub fn strip(file_name: &str) -> &str {
+        match Self::from_path(file_name) {
+            Some(_) => file_name
+                .rsplit_once('.')
+                .map_or(file_name, |(inner, _)| inner),
+            None => file_name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::bail;
+
+    use super::*;
+
+    #[test]
+    fn test_detect() -> anyhow::Result<()> {
+        let gzip = Compression::Gzip.compress(b"{}")?;
+        let zstd = Compression::Zstd.compress(b"{}")?;
+
+        let cases = [
+            ("spec.json.gz", b"".as_slice(), Some(Compression::Gzip)),
+            ("dump.yaml.zst", b"".as_slice(), Some(Compression::Zstd)),
+            ("spec.json", gzip.as_slice(), Some(Compression::Gzip)),
+            ("-", zstd.as_slice(), Some(Compression::Zstd)),
+            ("spec.json", b"{}".as_slice(), None),
+        ];
+
+        for (path, data, expected) in cases {
+            let compression = Compression::detect(path, data);
+            if compression != expected {
+                bail!(
+                    "Expected {:?} for {:?}, got {:?}",
+                    expected,
+                    path,
+                    compression
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let data = b"openapi: 3.0.0\ninfo:\n  title: Archived\n";
+
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let compressed = compression.compress(data)?;
+            if compressed.as_slice() == data.as_slice() {
+                bail!("Expected {:?} to change the data", compression);
+            }
+
+            let decompressed = compression.decompress(&compressed)?;
+            if decompressed.as_slice() != data.as_slice() {
+                bail!("Expected {:?}, got {:?}", data, decompressed);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip() -> anyhow::Result<()> {
+        let cases = [
+            ("spec.json.gz", "spec.json"),
+            ("dump.yaml.ZST", "dump.yaml"),
+            ("spec.json", "spec.json"),
+        ];
+
+        for (file_name, expected) in cases {
+            let stripped = Compression::strip(file_name);
+            if stripped != expected {
+                bail!("Expected {:?}, got {:?}", expected, stripped);
+            }
+        }
+
+        Ok(())
+    }
+}
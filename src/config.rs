@@ -0,0 +1,265 @@
+//! Settings read from `config.toml` at startup, every one of them optional.
+//!
+//! ```toml
+//! status_timeout = 2
+//! backup = true
+//!
+//! [search]
+//! cache_capacity = 1000
+//!
+//! [search.fuzzy]
+//! eq = 16
+//! target_gap = -3
+//! target_gap_extend = -1
+//!
+//! [log]
+//! dir = "/tmp"
+//! level = "info"
+//!
+//! [keys.nav]
+//! "<C-d>" = "down"
+//! ```
+
+use core::time::Duration;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context as _};
+use serde::Deserialize;
+
+use crate::keymap::Keymap;
+
+///
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Seconds a status message stays in the status bar.
+    pub status_timeout: u64,
+
+    /// Keep the previous contents of a file in `<file>.bak` on every save.
+    pub backup: bool,
+
+    ///
+    pub search: Search,
+
+    ///
+    pub log: Log,
+
+    /// Key bindings per page, on top of the defaults.
+    pub keys: Keymap,
+}
+
+///
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Search {
+    /// Number of queries whose results are kept around for narrowing them down.
+    pub cache_capacity: usize,
+
+    ///
+    pub fuzzy: Fuzzy,
+}
+
+/// Weights of the fuzzy matcher.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fuzzy {
+    /// Score of every matching character.
+    pub eq: i16,
+
+    /// Penalty for skipping characters of the target.
+    pub target_gap: i16,
+
+    /// Penalty for every character skipped after the first.
+    pub target_gap_extend: i16,
+}
+
+///
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Log {
+    /// Directory `rover.log` is written to, `ROVER_HOME` or `HOME` by default.
+    pub dir: Option<PathBuf>,
+
+    /// One of `off`, `error`, `warn`, `info`, `debug` or `trace`.
+    pub level: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            status_timeout: 2,
+            backup: false,
+            search: Search::default(),
+            log: Log::default(),
+            keys: Keymap::default(),
+        }
+    }
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            cache_capacity: 1000,
+            fuzzy: Fuzzy::default(),
+        }
+    }
+}
+
+impl Default for Fuzzy {
+    fn default() -> Self {
+        Self {
+            eq: 16,
+            target_gap: -3,
+            target_gap_extend: -1,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file, the defaults when there is none.
+    pub fn load() -> anyhow::Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents)
+                .with_context(|| format!("Invalid config file {}", path.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => {
+                Err(err).with_context(|| format!("Unable to read config file {}", path.display()))
+            }
+        }
+    }
+
+    /// `ROVER_CONFIG`, or `rover/config.toml` in `XDG_CONFIG_HOME` or `~/.config`.
+    fn path() -> Option<PathBuf> {
+        if let Ok(path) = env::var("ROVER_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+
+        let config_home = env::var("XDG_CONFIG_HOME")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var("HOME")
+                    .ok()
+                    .map(|home| Path::new(&home).join(".config"))
+            })?;
+
+        Some(config_home.join("rover").join("config.toml"))
+    }
+
+    ///
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(contents)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    ///
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.status_timeout == 0 {
+            bail!("status_timeout must be at least 1 second");
+        }
+        if self.search.cache_capacity == 0 {
+            bail!("search.cache_capacity must be at least 1");
+        }
+
+        let fuzzy = &self.search.fuzzy;
+        if fuzzy.eq <= 0 {
+            bail!("search.fuzzy.eq must be positive, got {}", fuzzy.eq);
+        }
+        if fuzzy.target_gap > 0 || fuzzy.target_gap_extend > 0 {
+            bail!("search.fuzzy.target_gap and search.fuzzy.target_gap_extend can't be positive");
+        }
+
+        self.log_level()?;
+
+        Ok(())
+    }
+
+    ///
+    pub const fn status_timeout(&self) -> Duration {
+        Duration::from_secs(self.status_timeout)
+    }
+
+    /// Whether saving keeps a `.bak` copy, `ROVER_BACKUP` overrides the config file.
+    pub fn backup(&self) -> bool {
+        env::var("ROVER_BACKUP").map_or(self.backup, |backup| !backup.is_empty() && backup != "0")
+    }
+
+    ///
+    pub fn log_level(&self) -> anyhow::Result<log::LevelFilter> {
+        self.log
+            .level
+            .as_deref()
+            .map_or(Ok(log::LevelFilter::Info), |level| {
+                level
+                    .parse()
+                    .with_context(|| format!("log.level can't be {level:?}"))
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::bail;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let config =
+            Config::parse("status_timeout = 5\nbackup = true\n\n[search.fuzzy]\neq = 20\n")?;
+
+        let expected = Config {
+            status_timeout: 5,
+            backup: true,
+            search: Search {
+                fuzzy: Fuzzy {
+                    eq: 20,
+                    ..Fuzzy::default()
+                },
+                ..Search::default()
+            },
+            ..Config::default()
+        };
+        if config != expected {
+            bail!("Expected {:?}, got {:?}", expected, config);
+        }
+
+        let config = Config::parse("")?;
+        if config != Config::default() {
+            bail!("Expected {:?}, got {:?}", Config::default(), config);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_invalid() -> anyhow::Result<()> {
+        let cases = [
+            "status_timeout = 0\n",
+            "status_timeout = \"2s\"\n",
+            "[search]\ncache_capacity = 0\n",
+            "[search]\ncapacity = 10\n",
+            "[search.fuzzy]\ntarget_gap = 3\n",
+            "[log]\nlevel = \"loud\"\n",
+            "[keys.nav]\nx = \"fly\"\n",
+        ];
+
+        for contents in cases {
+            if let Ok(config) = Config::parse(contents) {
+                bail!("Expected {:?} to be refused, got {:?}", contents, config);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+//! Problems with the `$ref`s of a document: pointers that don't parse, that point at nothing,
+//! or chains of `$ref`s going round in circles.
+//!
+//! A `$ref` back to one of its ancestors, like a recursive schema, is fine as long as the
+//! target is a value and not yet another `$ref`.
+
+use core::fmt;
+use std::collections::HashSet;
+
+use crate::{pointer::ValuePointer, search::references, state::ROOT_PATH, value::Value};
+
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// The pointer doesn't parse.
+    Invalid,
+
+    /// Nothing is at the pointer.
+    Dangling,
+
+    /// Following the `$ref`s from here never reaches a value.
+    Cycle,
+}
+
+/// A broken `$ref`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// Path of the map holding the `$ref`.
+    pub path: String,
+
+    ///
+    pub target: String,
+
+    ///
+    pub kind: Kind,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = &self.target;
+        match self.kind {
+            Kind::Invalid => write!(f, "Invalid pointer {target}"),
+            Kind::Dangling => write!(f, "Nothing at {target}"),
+            Kind::Cycle => write!(f, "Circular, {target} never leads to a value"),
+        }
+    }
+}
+
+/// Every broken `$ref` into `doc`, in document order. References to other files aren't
+/// checked.
+pub fn check(doc: &Value) -> Vec<Problem> {
+    references(doc)
+        .into_iter()
+        .filter_map(|(path, target)| {
+            let kind = diagnose(doc, &path, &target)?;
+            Some(Problem { path, target, kind })
+        })
+        .collect()
+}
+
+/// Parses `target`, which has to be `#` or start with `#/`.
+fn parse(target: &str) -> Option<ValuePointer> {
+    let valid = target == ROOT_PATH
+        || target
+            .strip_prefix(ROOT_PATH)
+            .is_some_and(|pointer| pointer.starts_with('/'));
+
+    valid.then(|| target.parse().ok()).flatten()
+}
+
+/// What is wrong with the `$ref` at `path` to `target`, if anything.
+fn diagnose(doc: &Value, path: &str, target: &str) -> Option<Kind> {
+    let Some(pointer) = parse(target) else {
+        return Some(Kind::Invalid);
+    };
+    let Ok(mut value) = pointer.get(doc) else {
+        return Some(Kind::Dangling);
+    };
+
+    let mut seen = HashSet::from([path]);
+    let mut next = target;
+    loop {
+        if !seen.insert(next) {
+            return Some(Kind::Cycle);
+        }
+
+        // Broken links further down the chain are reported where they are
+        let &Value::Object(ref map) = value else {
+            return None;
+        };
+        let Some(&Value::String(ref target)) = map.get("$ref") else {
+            return None;
+        };
+        value = parse(target)?.get(doc).ok()?;
+        next = target;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::bail;
+
+    use super::*;
+
+    #[test]
+    fn test_check() -> anyhow::Result<()> {
+        let doc: Value = serde_yaml::from_str(
+            "
+ok: {$ref: '#/defs/Node'}
+dangling: {$ref: '#/defs/Missing'}
+invalid: {$ref: '#/defs/~2'}
+relative: {$ref: '#defs'}
+external: {$ref: 'other.yaml#/Missing'}
+self: {$ref: '#/self'}
+defs:
+  Node:
+    properties:
+      child: {$ref: '#/defs/Node'}
+  A: {$ref: '#/defs/B'}
+  B: {$ref: '#/defs/A'}
+  C: {$ref: '#/defs/A'}
+",
+        )?;
+
+        let problems = check(&doc)
+            .into_iter()
+            .map(|problem| (problem.path, problem.kind))
+            .collect::<Vec<_>>();
+        let expected = [
+            ("#/dangling", Kind::Dangling),
+            ("#/invalid", Kind::Invalid),
+            ("#/relative", Kind::Invalid),
+            ("#/self", Kind::Cycle),
+            ("#/defs/A", Kind::Cycle),
+            ("#/defs/B", Kind::Cycle),
+            ("#/defs/C", Kind::Cycle),
+        ]
+        .map(|(path, kind)| (path.to_owned(), kind));
+        if problems != expected {
+            bail!("Expected {:?}, got {:?}", expected, problems);
+        }
+
+        Ok(())
+    }
+}
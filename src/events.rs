@@ -6,38 +6,50 @@ extern crate alloc;
 use alloc::sync::Arc;
 use anyhow::anyhow;
 
-use core::time::Duration;
+use core::{ops::ControlFlow, time::Duration};
 
 use std::{
     env,
     fs::{self, File},
     io::{self, Write as _},
-    path::PathBuf,
     sync::Mutex,
 };
 
-use crossterm::event::{self, poll, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{self, poll, Event};
 use redux_rs::{Reducer, Store};
 use tui::backend::Backend;
 
 use crate::{
     action::Action,
+    compression::Compression,
+    ex::{self, Ex},
+    format::Format,
+    keymap::{Command, Key, Lookup},
     lifecycle::Application,
+    marks,
     pointer::ValuePointer,
-    state::{Page, State, StatusMessage},
-    util::{editor, save_doc},
+    reference,
+    state::{Page, State, StatusMessage, EXTERNAL_WARNING, READ_ONLY_WARNING},
+    util::{drops_comments, editor, fetch_document, save_doc, Persisted, STDIN},
     value::Value,
 };
 
 ///
 pub async fn event_listener<R, B>(
-    store: Store<State, Action, R>,
+    store: &Store<State, Action, R>,
     lifecycle: Arc<Mutex<Application<B>>>,
 ) -> anyhow::Result<()>
 where
     R: Reducer<State, Action> + Send + Sync + 'static,
     B: Backend + io::Write + Send + Sync + 'static,
 {
+    let keymap = store
+        .select(|state: &State| state.config.keys.clone())
+        .await;
+    let mut pending: Vec<Key> = Vec::new();
+    // Set or jump to a mark, waiting for its letter
+    let mut awaiting: Option<Command> = None;
+
     loop {
         let status_timeout = store.select(|state: &State| state.status.timeout).await;
         if let Some(status_timeout) = status_timeout {
@@ -69,822 +81,1503 @@ where
                 continue;
             }
 
+            let Event::Key(key) = read_event else {
+                continue;
+            };
+
+            if let Some(command) = awaiting.take() {
+                mark(store, command, Key::from(key).text()).await;
+                continue;
+            }
+
             let current_view = store.select(|state: &State| state.current_page).await;
-            match current_view {
-                Page::Nav => {
-                    if let Event::Key(key) = read_event {
-                        match key {
-                            KeyEvent {
-                                code: KeyCode::Char('h') | KeyCode::Backspace | KeyCode::Left,
-                                ..
-                            } => store.dispatch(Action::NavBack).await,
-                            KeyEvent {
-                                code: KeyCode::Char('j') | KeyCode::Down,
-                                ..
-                            }
-                            | KeyEvent {
-                                code: KeyCode::Char('n'),
-                                modifiers: KeyModifiers::CONTROL,
-                                ..
-                            } => store.dispatch(Action::NavDown).await,
-                            KeyEvent {
-                                code: KeyCode::Char('J'),
-                                ..
-                            } => store.dispatch(Action::NavMoveDown).await,
-                            KeyEvent {
-                                code: KeyCode::Char('k') | KeyCode::Up,
-                                ..
-                            }
-                            | KeyEvent {
-                                code: KeyCode::Char('p'),
-                                modifiers: KeyModifiers::CONTROL,
-                                ..
-                            } => store.dispatch(Action::NavUp).await,
-                            KeyEvent {
-                                code: KeyCode::Char('K'),
-                                ..
-                            } => store.dispatch(Action::NavMoveUp).await,
-                            KeyEvent {
-                                code: KeyCode::Char('l') | KeyCode::Enter | KeyCode::Right,
-                                ..
-                            } => {
-                                let children = store
-                                    .select(|state: &State| {
-                                        state
-                                            .nav_state
-                                            .current
-                                            .path
-                                            .parse::<ValuePointer>()
-                                            .ok()
-                                            .and_then(|pointer| pointer.get(&state.doc).ok())
-                                            .and_then(|value| match value {
-                                                &Value::Array(ref arr) => {
-                                                    arr.get(state.nav_state.current.selected)
-                                                }
-                                                &Value::Object(ref obj) => obj
-                                                    .get_index(state.nav_state.current.selected)
-                                                    .map(|(_, v)| v),
-                                                &Value::Null
-                                                | &Value::Bool(_)
-                                                | &Value::String(_)
-                                                | &Value::Number(_) => None,
-                                            })
-                                            .map_or(0, |child| match child {
-                                                &Value::Array(ref arr) => arr.len(),
-                                                &Value::Object(ref obj) => obj.len(),
-                                                &Value::Null
-                                                | &Value::Bool(_)
-                                                | &Value::String(_)
-                                                | &Value::Number(_) => 0,
-                                            })
-                                    })
-                                    .await;
-
-                                if children > 0 {
-                                    store.dispatch(Action::NavSelect).await;
-                                } else {
-                                    store
-                                        .dispatch(Action::SetStatus {
-                                            message: StatusMessage::Warn(
-                                                "No children to select, use ^e to edit this value"
-                                                    .to_owned(),
-                                            ),
-                                            timeout: Some(Duration::from_secs(2)),
-                                        })
-                                        .await;
-                                }
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('I'),
-                                ..
-                            } => {
-                                let cwd = env::current_dir()?.to_string_lossy().to_string();
-                                store
-                                    .dispatch(Action::ImportPromptSetValue { value: cwd })
-                                    .await;
-                                store
-                                    .dispatch(Action::SetCurrentPage {
-                                        page: Page::ImportPrompt,
-                                    })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('O'),
-                                ..
-                            } => {
-                                let cwd = env::current_dir()?.to_string_lossy().to_string();
-                                store
-                                    .dispatch(Action::ExportPromptSetValue { value: cwd })
-                                    .await;
-                                store
-                                    .dispatch(Action::SetCurrentPage {
-                                        page: Page::ExportPrompt,
-                                    })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('u'),
-                                ..
-                            } => {
-                                store.dispatch(Action::Undo).await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('r'),
-                                ..
-                            } => {
-                                store.dispatch(Action::Redo).await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('e'),
-                                modifiers: KeyModifiers::CONTROL,
-                                ..
-                            } => {
-                                let existing_value = store
-                                    .select(|state: &State| {
-                                        state
-                                            .nav_state
-                                            .current
-                                            .path
-                                            .parse::<ValuePointer>()
-                                            .ok()
-                                            .and_then(|pointer| pointer.get(&state.doc).ok())
-                                            .and_then(|value| match value {
-                                                &Value::Array(ref arr) => {
-                                                    arr.get(state.nav_state.current.selected)
-                                                }
-                                                &Value::Object(ref obj) => obj
-                                                    .get_index(state.nav_state.current.selected)
-                                                    .map(|(_, v)| v),
-                                                &Value::Null
-                                                | &Value::Bool(_)
-                                                | &Value::String(_)
-                                                | &Value::Number(_) => None,
-                                            })
-                                            .cloned()
-                                            .unwrap_or(Value::Null)
-                                    })
-                                    .await;
-
-                                {
-                                    let mut lifecycle = lifecycle.lock().map_err(|e| {
-                                        anyhow!("Unable to get lifecycle lock: {e}")
-                                    })?;
-                                    lifecycle.suspend()?;
-                                };
-
-                                let file_name =
-                                    store.select(|state: &State| state.file_name.clone()).await;
-                                let new_value = editor(&existing_value, &file_name);
-
-                                {
-                                    let mut lifecycle = lifecycle.lock().map_err(|e| {
-                                        anyhow!("Unable to get lifecycle lock: {e}")
-                                    })?;
-                                    lifecycle.resume()?;
-                                };
-
-                                match new_value {
-                                    Ok(new_value) => {
-                                        store
-                                            .dispatch(Action::DocumentReplaceCurrent {
-                                                value: new_value,
-                                            })
-                                            .await;
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Ok(
-                                                    "Successfully edited value".to_owned(),
-                                                ),
-                                                timeout: Some(Duration::from_secs(2)),
-                                            })
-                                            .await;
-                                    }
-                                    Err(e) => {
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Err(format!(
-                                                    "Unable to edit value: {e}"
-                                                )),
-                                                timeout: None,
-                                            })
-                                            .await;
-                                    }
-                                }
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('s'),
-                                modifiers: KeyModifiers::CONTROL,
-                                ..
-                            } => {
-                                let file_name =
-                                    store.select(|state: &State| state.file_name.clone()).await;
-                                let doc = store.select(|state: &State| state.doc.clone()).await;
-                                let result = save_doc(&file_name, &doc);
-
-                                match result {
-                                    Ok(()) => {
-                                        store.dispatch(Action::Snapshot).await;
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Ok(
-                                                    "Successfully saved file".to_owned(),
-                                                ),
-                                                timeout: Some(Duration::from_secs(2)),
-                                            })
-                                            .await;
-                                    }
-                                    Err(e) => {
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Err(format!(
-                                                    "Unable to save file: {e}"
-                                                )),
-                                                timeout: None,
-                                            })
-                                            .await;
-                                    }
-                                }
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('g'),
-                                ..
-                            } => store.dispatch(Action::NavTop).await,
-                            KeyEvent {
-                                code: KeyCode::Char('G'),
-                                ..
-                            } => store.dispatch(Action::NavBottom).await,
-                            KeyEvent {
-                                code: KeyCode::Char('/'),
-                                ..
-                            } => {
-                                store.dispatch(Action::SearchSetAllPaths).await;
-                                store
-                                    .dispatch(Action::SetCurrentPage { page: Page::Search })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('q'),
-                                modifiers: KeyModifiers::CONTROL,
-                                ..
-                            } => return Ok(()),
-                            KeyEvent {
-                                code: KeyCode::Char('q') | KeyCode::Esc,
-                                ..
-                            } => {
-                                let undo_length =
-                                    store.select(|state: &State| state.undo_stack.len()).await;
-
-                                if undo_length == 0 {
-                                    return Ok(());
-                                }
-
-                                store
-                                    .dispatch(Action::SetStatus {
-                                        message: StatusMessage::Warn(
-                                            "Unsaved changes, press ^q to quit without saving"
-                                                .to_owned(),
-                                        ),
-                                        timeout: Some(Duration::from_secs(2)),
-                                    })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('c'),
-                                modifiers: KeyModifiers::CONTROL,
-                                ..
-                            } => {
-                                let empty_status = store
-                                    .select(|state: &State| {
-                                        matches!(state.status.message, StatusMessage::Empty)
-                                    })
-                                    .await;
-                                let undo_length =
-                                    store.select(|state: &State| state.undo_stack.len()).await;
-                                if empty_status {
-                                    if undo_length == 0 {
-                                        return Ok(());
-                                    }
-
-                                    store
-                                        .dispatch(Action::SetStatus {
-                                            message: StatusMessage::Warn(
-                                                "Unsaved changes, press ^q to quit without saving"
-                                                    .to_owned(),
-                                            ),
-                                            timeout: Some(Duration::from_secs(2)),
-                                        })
-                                        .await;
-                                } else {
-                                    store
-                                        .dispatch(Action::SetStatus {
-                                            message: StatusMessage::Empty,
-                                            timeout: None,
-                                        })
-                                        .await;
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
+            pending.push(Key::from(key));
+
+            let mut lookup = keymap.lookup(current_view, &pending);
+            // A sequence that went nowhere, start over from its last key. The keys before
+            // it are still text typed into an input.
+            if lookup == Lookup::Unbound && pending.len() > 1 {
+                let typed: String = pending
+                    .drain(..pending.len().saturating_sub(1))
+                    .filter_map(Key::text)
+                    .collect();
+                if !typed.is_empty() {
+                    input(store, current_view, |value| value.push_str(&typed)).await;
                 }
-                Page::ImportPrompt => {
-                    if let Event::Key(key) = read_event {
-                        match key {
-                            KeyEvent {
-                                code: KeyCode::Char(ch),
-                                modifiers: KeyModifiers::SHIFT | KeyModifiers::NONE,
-                                ..
-                            } => {
-                                let mut current = store
-                                    .select(|state: &State| state.import_prompt_state.value.clone())
-                                    .await;
-                                current.push(ch);
-                                store
-                                    .dispatch(Action::ImportPromptSetValue { value: current })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Backspace,
-                                ..
-                            } => {
-                                let mut current = store
-                                    .select(|state: &State| state.import_prompt_state.value.clone())
-                                    .await;
-                                current.pop();
-                                store
-                                    .dispatch(Action::ImportPromptSetValue { value: current })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Enter,
-                                ..
-                            } => {
-                                let current_path = store
-                                    .select(|state: &State| state.import_prompt_state.value.clone())
-                                    .await;
-
-                                let existing_value = match fs::read_to_string(&current_path) {
-                                    Ok(existing_value) => existing_value,
-                                    Err(e) => {
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Err(format!(
-                                                    "Unable to read file: {e}"
-                                                )),
-                                                timeout: None,
-                                            })
-                                            .await;
-                                        store
-                                            .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                            .await;
-                                        continue;
-                                    }
-                                };
-
-                                let extention = PathBuf::from(&current_path);
-                                let Some(extention) =
-                                    extention.extension().map(std::ffi::OsStr::to_string_lossy)
-                                else {
-                                    store
-                                        .dispatch(Action::SetStatus {
-                                            message: StatusMessage::Err(
-                                                "Unable to determine file type".to_owned(),
-                                            ),
-                                            timeout: None,
-                                        })
-                                        .await;
-                                    store
-                                        .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                        .await;
-                                    continue;
-                                };
-
-                                let existing_value = match extention.as_ref() {
-                                    "yaml" | "yml" => match serde_yaml::from_str(&existing_value) {
-                                        Ok(existing_value) => existing_value,
-                                        Err(e) => {
-                                            store
-                                                .dispatch(Action::SetStatus {
-                                                    message: StatusMessage::Err(format!(
-                                                        "Unable to parse file: {e}"
-                                                    )),
-                                                    timeout: None,
-                                                })
-                                                .await;
-                                            store
-                                                .dispatch(Action::SetCurrentPage {
-                                                    page: Page::Nav,
-                                                })
-                                                .await;
-                                            continue;
-                                        }
-                                    },
-                                    "json" => match serde_json::from_str(&existing_value) {
-                                        Ok(existing_value) => existing_value,
-                                        Err(e) => {
-                                            store
-                                                .dispatch(Action::SetStatus {
-                                                    message: StatusMessage::Err(format!(
-                                                        "Unable to parse file: {e}"
-                                                    )),
-                                                    timeout: None,
-                                                })
-                                                .await;
-                                            store
-                                                .dispatch(Action::SetCurrentPage {
-                                                    page: Page::Nav,
-                                                })
-                                                .await;
-                                            continue;
-                                        }
-                                    },
-                                    _ => {
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Err(format!(
-                                                    "Unsupported file type: {extention}"
-                                                )),
-                                                timeout: None,
-                                            })
-                                            .await;
-                                        store
-                                            .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                            .await;
-                                        continue;
-                                    }
-                                };
-
-                                {
-                                    let mut lifecycle = lifecycle.lock().map_err(|e| {
-                                        anyhow!("Unable to get lifecycle lock: {e}")
-                                    })?;
-                                    lifecycle.suspend()?;
-                                };
-
-                                let file_name =
-                                    store.select(|state: &State| state.file_name.clone()).await;
-                                let new_value = editor(&existing_value, &file_name);
-
-                                {
-                                    let mut lifecycle = lifecycle.lock().map_err(|e| {
-                                        anyhow!("Unable to get lifecycle lock: {e}")
-                                    })?;
-                                    lifecycle.resume()?;
-                                };
-
-                                match new_value {
-                                    Ok(new_value) => {
-                                        store
-                                            .dispatch(Action::DocumentReplaceCurrent {
-                                                value: new_value,
-                                            })
-                                            .await;
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Ok(
-                                                    "Successfully edited value".to_owned(),
-                                                ),
-                                                timeout: Some(Duration::from_secs(2)),
-                                            })
-                                            .await;
-                                    }
-                                    Err(e) => {
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Err(format!(
-                                                    "Unable to edit value: {e}"
-                                                )),
-                                                timeout: None,
-                                            })
-                                            .await;
-                                    }
-                                }
-
-                                store
-                                    .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('c'),
-                                modifiers: KeyModifiers::CONTROL,
-                                ..
-                            }
-                            | KeyEvent {
-                                code: KeyCode::Esc, ..
-                            } => {
-                                store
-                                    .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                    .await;
-                            }
-                            _ => {}
-                        }
-                    }
+                lookup = keymap.lookup(current_view, &pending);
+            }
+
+            match lookup {
+                Lookup::Command(command @ (Command::SetMark | Command::JumpToMark)) => {
+                    pending.clear();
+                    awaiting = Some(command);
                 }
-                Page::ExportPrompt => {
-                    if let Event::Key(key) = read_event {
-                        match key {
-                            KeyEvent {
-                                code: KeyCode::Char(ch),
-                                modifiers: KeyModifiers::SHIFT | KeyModifiers::NONE,
-                                ..
-                            } => {
-                                let mut current = store
-                                    .select(|state: &State| state.export_prompt_state.value.clone())
-                                    .await;
-                                current.push(ch);
-                                store
-                                    .dispatch(Action::ExportPromptSetValue { value: current })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Backspace,
-                                ..
-                            } => {
-                                let mut current = store
-                                    .select(|state: &State| state.export_prompt_state.value.clone())
-                                    .await;
-                                current.pop();
-                                store
-                                    .dispatch(Action::ExportPromptSetValue { value: current })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Enter,
-                                ..
-                            } => {
-                                let existing_value = store
-                                    .select(|state: &State| {
-                                        state
-                                            .nav_state
-                                            .current
-                                            .path
-                                            .parse::<ValuePointer>()
-                                            .ok()
-                                            .and_then(|pointer| pointer.get(&state.doc).ok())
-                                            .and_then(|value| match value {
-                                                &Value::Array(ref arr) => {
-                                                    arr.get(state.nav_state.current.selected)
-                                                }
-                                                &Value::Object(ref obj) => obj
-                                                    .get_index(state.nav_state.current.selected)
-                                                    .map(|(_, v)| v),
-                                                &Value::Null
-                                                | &Value::Bool(_)
-                                                | &Value::String(_)
-                                                | &Value::Number(_) => None,
-                                            })
-                                            .cloned()
-                                            .unwrap_or(Value::Null)
-                                    })
-                                    .await;
-
-                                let current_path = store
-                                    .select(|state: &State| state.export_prompt_state.value.clone())
-                                    .await;
-
-                                let extention = PathBuf::from(&current_path);
-                                let Some(extention) =
-                                    extention.extension().map(std::ffi::OsStr::to_string_lossy)
-                                else {
-                                    store
-                                        .dispatch(Action::SetStatus {
-                                            message: StatusMessage::Err(
-                                                "Unable to determine file type".to_owned(),
-                                            ),
-                                            timeout: None,
-                                        })
-                                        .await;
-                                    store
-                                        .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                        .await;
-                                    continue;
-                                };
-
-                                let existing_value = match extention.as_ref() {
-                                    "yaml" | "yml" => {
-                                        match serde_yaml::to_string(&existing_value) {
-                                            Ok(existing_value) => existing_value,
-                                            Err(e) => {
-                                                store
-                                                    .dispatch(Action::SetStatus {
-                                                        message: StatusMessage::Err(format!(
-                                                            "Unable to serialize value: {e}"
-                                                        )),
-                                                        timeout: None,
-                                                    })
-                                                    .await;
-                                                store
-                                                    .dispatch(Action::SetCurrentPage {
-                                                        page: Page::Nav,
-                                                    })
-                                                    .await;
-                                                continue;
-                                            }
-                                        }
-                                    }
-                                    "json" => match serde_json::to_string_pretty(&existing_value) {
-                                        Ok(existing_value) => existing_value,
-                                        Err(e) => {
-                                            store
-                                                .dispatch(Action::SetStatus {
-                                                    message: StatusMessage::Err(format!(
-                                                        "Unable to serialize value: {e}"
-                                                    )),
-                                                    timeout: None,
-                                                })
-                                                .await;
-                                            store
-                                                .dispatch(Action::SetCurrentPage {
-                                                    page: Page::Nav,
-                                                })
-                                                .await;
-                                            continue;
-                                        }
-                                    },
-                                    _ => {
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Err(format!(
-                                                    "Unsupported file type: {extention}"
-                                                )),
-                                                timeout: None,
-                                            })
-                                            .await;
-                                        store
-                                            .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                            .await;
-                                        continue;
-                                    }
-                                };
-
-                                let mut file = match File::create(&current_path) {
-                                    Ok(file) => file,
-                                    Err(e) => {
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Err(format!(
-                                                    "Unable to create file: {e}"
-                                                )),
-                                                timeout: None,
-                                            })
-                                            .await;
-                                        store
-                                            .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                            .await;
-                                        continue;
-                                    }
-                                };
-
-                                match file.write_all(existing_value.as_bytes()) {
-                                    Ok(()) => {
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Ok(
-                                                    "Successfully exported value".to_owned(),
-                                                ),
-                                                timeout: Some(Duration::from_secs(2)),
-                                            })
-                                            .await;
-                                    }
-                                    Err(e) => {
-                                        store
-                                            .dispatch(Action::SetStatus {
-                                                message: StatusMessage::Err(format!(
-                                                    "Unable to write file: {e}"
-                                                )),
-                                                timeout: None,
-                                            })
-                                            .await;
-                                    }
-                                }
-
-                                store
-                                    .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('c'),
-                                modifiers: KeyModifiers::CONTROL,
-                                ..
-                            }
-                            | KeyEvent {
-                                code: KeyCode::Esc, ..
-                            } => {
-                                store
-                                    .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                    .await;
-                            }
-                            _ => {}
-                        }
+                Lookup::Command(command) => {
+                    pending.clear();
+                    if run(store, &lifecycle, current_view, command)
+                        .await?
+                        .is_break()
+                    {
+                        return Ok(());
                     }
                 }
-                Page::Search => {
-                    if let Event::Key(key) = read_event {
-                        match key {
-                            KeyEvent {
-                                code: KeyCode::Char(ch),
-                                modifiers: KeyModifiers::SHIFT | KeyModifiers::NONE,
-                                ..
-                            } => {
-                                let mut current = store
-                                    .select(|state: &State| state.search_state.value.clone())
-                                    .await;
-                                current.push(ch);
-                                store
-                                    .dispatch(Action::SearchSetValue { value: current })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Backspace,
-                                ..
-                            } => {
-                                let mut current = store
-                                    .select(|state: &State| state.search_state.value.clone())
-                                    .await;
-                                current.pop();
-                                store
-                                    .dispatch(Action::SearchSetValue { value: current })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Enter,
-                                ..
-                            } => {
-                                let selected_path = store
-                                    .select(|state: &State| {
-                                        state
-                                            .search_state
-                                            .filtered_paths
-                                            .get(state.search_state.selected)
-                                            .cloned()
-                                    })
-                                    .await;
-
-                                if let Some(selected_path) = selected_path {
-                                    store
-                                        .dispatch(Action::NavGoto {
-                                            path: selected_path,
-                                        })
-                                        .await;
-                                }
-
-                                store
-                                    .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                    .await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('n'),
-                                modifiers: KeyModifiers::CONTROL,
-                                ..
-                            }
-                            | KeyEvent {
-                                code: KeyCode::Down,
-                                ..
-                            } => {
-                                store.dispatch(Action::SearchDown).await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Char('p'),
-                                modifiers: KeyModifiers::CONTROL,
-                                ..
-                            }
-                            | KeyEvent {
-                                code: KeyCode::Up, ..
-                            } => {
-                                store.dispatch(Action::SearchUp).await;
-                            }
-                            KeyEvent {
-                                code: KeyCode::Esc, ..
-                            }
-                            | KeyEvent {
-                                code: KeyCode::Char('c'),
-                                modifiers: KeyModifiers::CONTROL,
-                                ..
-                            } => {
-                                let empty_status = store
-                                    .select(|state: &State| {
-                                        matches!(state.status.message, StatusMessage::Empty)
-                                    })
-                                    .await;
-                                if empty_status {
-                                    store
-                                        .dispatch(Action::SetCurrentPage { page: Page::Nav })
-                                        .await;
-                                    continue;
-                                }
-
-                                store
-                                    .dispatch(Action::SetStatus {
-                                        message: StatusMessage::Empty,
-                                        timeout: None,
-                                    })
-                                    .await;
-                            }
-                            _ => {}
-                        }
+                Lookup::Pending => {}
+                Lookup::Unbound => {
+                    let typed = pending.pop().and_then(Key::text);
+                    pending.clear();
+                    if let Some(ch) = typed {
+                        input(store, current_view, |value| value.push(ch)).await;
                     }
                 }
             }
         }
     }
 }
+
+/// Runs `command` on `page`, breaking when rover should exit.
+async fn run<R, B>(
+    store: &Store<State, Action, R>,
+    lifecycle: &Mutex<Application<B>>,
+    page: Page,
+    command: Command,
+) -> anyhow::Result<ControlFlow<()>>
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+    B: Backend + io::Write + Send + Sync + 'static,
+{
+    let picking = store.select(|state: &State| state.pick.is_some()).await;
+    let read_only = store.select(|state: &State| state.read_only).await;
+    let external = store
+        .select(|state: &State| state.nav_state.current.file.is_some())
+        .await;
+    let status_timeout = store
+        .select(|state: &State| state.config.status_timeout())
+        .await;
+
+    match (page, command) {
+        (Page::Nav, Command::Confirm) if picking && external => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Warn(
+                        "Only values of the document can be picked, go back to pick one".to_owned(),
+                    ),
+                    timeout: Some(status_timeout),
+                })
+                .await;
+        }
+        (Page::Nav, Command::Edit | Command::Import) if external => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Warn(EXTERNAL_WARNING.to_owned()),
+                    timeout: Some(status_timeout),
+                })
+                .await;
+        }
+        (Page::Nav, Command::Confirm) if picking => {
+            let path = store.select(State::highlighted_path).await;
+            if let Some(path) = path {
+                store.dispatch(Action::Pick { path }).await;
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        (Page::Nav, Command::Edit | Command::Save | Command::Import) if read_only => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Warn(READ_ONLY_WARNING.to_owned()),
+                    timeout: Some(status_timeout),
+                })
+                .await;
+        }
+        (Page::Nav, Command::Back) => store.dispatch(Action::NavBack).await,
+        (Page::Nav, Command::Down) => store.dispatch(Action::NavDown).await,
+        (Page::Nav, Command::MoveDown) => store.dispatch(Action::NavMoveDown).await,
+        (Page::Nav, Command::Up) => store.dispatch(Action::NavUp).await,
+        (Page::Nav, Command::MoveUp) => store.dispatch(Action::NavMoveUp).await,
+        (Page::Nav, Command::Confirm | Command::Select) => select(store, status_timeout).await?,
+        (Page::Nav, Command::Import) => {
+            let cwd = env::current_dir()?.to_string_lossy().to_string();
+            store
+                .dispatch(Action::ImportPromptSetValue { value: cwd })
+                .await;
+            store
+                .dispatch(Action::SetCurrentPage {
+                    page: Page::ImportPrompt,
+                })
+                .await;
+        }
+        (Page::Nav, Command::Export) => {
+            let cwd = env::current_dir()?.to_string_lossy().to_string();
+            store
+                .dispatch(Action::ExportPromptSetValue { value: cwd })
+                .await;
+            store
+                .dispatch(Action::SetCurrentPage {
+                    page: Page::ExportPrompt,
+                })
+                .await;
+        }
+        (Page::Nav, Command::Undo) => store.dispatch(Action::Undo).await,
+        (Page::Nav, Command::Redo) => store.dispatch(Action::Redo).await,
+        (Page::Nav, Command::Edit) => edit(store, lifecycle, status_timeout).await?,
+        (Page::Nav, Command::Save) => {
+            save(store, status_timeout).await?;
+        }
+        (Page::Nav, Command::SaveAs) => {
+            let mut file_name = store.select(|state: &State| state.file_name.clone()).await;
+            if file_name == STDIN {
+                file_name.clear();
+            }
+            store
+                .dispatch(Action::SaveAsPromptSetValue { value: file_name })
+                .await;
+            store
+                .dispatch(Action::SetCurrentPage {
+                    page: Page::SaveAsPrompt,
+                })
+                .await;
+        }
+        (Page::Nav, Command::JumpBack) => store.dispatch(Action::JumpBack).await,
+        (Page::Nav, Command::JumpForward) => store.dispatch(Action::JumpForward).await,
+        (Page::Nav, Command::Top) => store.dispatch(Action::NavTop).await,
+        (Page::Nav, Command::Bottom) => store.dispatch(Action::NavBottom).await,
+        (Page::Nav, Command::Search) => {
+            store.dispatch(Action::SearchSetAllPaths).await;
+            store
+                .dispatch(Action::SetCurrentPage { page: Page::Search })
+                .await;
+        }
+        (Page::Nav, Command::ForceQuit) => return Ok(ControlFlow::Break(())),
+        (Page::Nav, Command::Quit) => return Ok(quit(store, status_timeout).await),
+        (Page::Nav, Command::Cancel) => {
+            let empty_status = store
+                .select(|state: &State| matches!(state.status.message, StatusMessage::Empty))
+                .await;
+            let undo_length = store.select(|state: &State| state.undo_stack.len()).await;
+            if empty_status {
+                if undo_length == 0 {
+                    return Ok(ControlFlow::Break(()));
+                }
+
+                store
+                    .dispatch(Action::SetStatus {
+                        message: StatusMessage::Warn(
+                            "Unsaved changes, press ^q to quit without saving".to_owned(),
+                        ),
+                        timeout: Some(status_timeout),
+                    })
+                    .await;
+            } else {
+                store
+                    .dispatch(Action::SetStatus {
+                        message: StatusMessage::Empty,
+                        timeout: None,
+                    })
+                    .await;
+            }
+        }
+        (Page::ImportPrompt, Command::Submit) => import(store, lifecycle, status_timeout).await?,
+        (Page::ExportPrompt, Command::Submit) => export(store, status_timeout).await?,
+        (Page::SaveAsPrompt, Command::Submit) => {
+            save_as(store, status_timeout).await?;
+        }
+        (
+            Page::ImportPrompt
+            | Page::ExportPrompt
+            | Page::SaveAsPrompt
+            | Page::CommandLine
+            | Page::Marks
+            | Page::Usages
+            | Page::Problems,
+            Command::Cancel,
+        ) => {
+            store
+                .dispatch(Action::SetCurrentPage { page: Page::Nav })
+                .await;
+        }
+        (Page::Search, Command::Submit) => {
+            let selected_path = store
+                .select(|state: &State| {
+                    state
+                        .search_state
+                        .filtered_paths
+                        .get(state.search_state.selected)
+                        .cloned()
+                })
+                .await;
+
+            if let Some(selected_path) = selected_path {
+                if picking {
+                    store
+                        .dispatch(Action::Pick {
+                            path: selected_path,
+                        })
+                        .await;
+                    return Ok(ControlFlow::Break(()));
+                }
+
+                store
+                    .dispatch(Action::NavGoto {
+                        path: selected_path,
+                    })
+                    .await;
+            }
+
+            store
+                .dispatch(Action::SetCurrentPage { page: Page::Nav })
+                .await;
+        }
+        (Page::Search, Command::Down) => store.dispatch(Action::SearchDown).await,
+        (Page::Search, Command::Up) => store.dispatch(Action::SearchUp).await,
+        (Page::Search, Command::Cancel) => {
+            let empty_status = store
+                .select(|state: &State| matches!(state.status.message, StatusMessage::Empty))
+                .await;
+            if empty_status {
+                store
+                    .dispatch(Action::SetCurrentPage { page: Page::Nav })
+                    .await;
+            } else {
+                store
+                    .dispatch(Action::SetStatus {
+                        message: StatusMessage::Empty,
+                        timeout: None,
+                    })
+                    .await;
+            }
+        }
+        (Page::Nav, Command::OpenCommandLine) => {
+            store
+                .dispatch(Action::CommandLineSetValue {
+                    value: String::new(),
+                })
+                .await;
+            store
+                .dispatch(Action::SetCurrentPage {
+                    page: Page::CommandLine,
+                })
+                .await;
+        }
+        (Page::Nav, Command::Marks) => open_marks(store).await,
+        (Page::Marks, Command::Submit) => {
+            let path = store
+                .select(|state: &State| {
+                    state
+                        .marks
+                        .values()
+                        .nth(state.marks_state.selected)
+                        .cloned()
+                })
+                .await;
+            return Ok(open_result(store, path, picking).await);
+        }
+        (Page::Nav, Command::FindUsages) => find_usages(store, status_timeout).await,
+        (Page::Usages, Command::Submit) => {
+            let path = store
+                .select(|state: &State| {
+                    state
+                        .usages_state
+                        .results
+                        .get(state.usages_state.selected)
+                        .cloned()
+                })
+                .await;
+            return Ok(open_result(store, path, picking).await);
+        }
+        (Page::Usages, Command::Down) => store.dispatch(Action::UsagesDown).await,
+        (Page::Usages, Command::Up) => store.dispatch(Action::UsagesUp).await,
+        (Page::Nav, Command::Problems) => open_problems(store, status_timeout).await,
+        (Page::Problems, Command::Submit) => {
+            let path = store
+                .select(|state: &State| {
+                    state
+                        .problems
+                        .get(state.problems_state.selected)
+                        .map(|problem| problem.path.clone())
+                })
+                .await;
+            return Ok(open_result(store, path, picking).await);
+        }
+        (Page::Problems, Command::Down) => store.dispatch(Action::ProblemsDown).await,
+        (Page::Problems, Command::Up) => store.dispatch(Action::ProblemsUp).await,
+        (Page::Marks, Command::Down) => store.dispatch(Action::MarksDown).await,
+        (Page::Marks, Command::Up) => store.dispatch(Action::MarksUp).await,
+        (Page::Marks, Command::DeleteMark) => {
+            store.dispatch(Action::DeleteMark).await;
+            save_marks(store).await;
+        }
+        (Page::CommandLine, Command::Submit) => {
+            let line = store
+                .select(|state: &State| state.command_line_state.value.clone())
+                .await;
+            store
+                .dispatch(Action::CommandLineRecord { line: line.clone() })
+                .await;
+            store
+                .dispatch(Action::SetCurrentPage { page: Page::Nav })
+                .await;
+
+            if !line.trim().is_empty() {
+                return execute(store, lifecycle, &line, status_timeout).await;
+            }
+        }
+        (Page::CommandLine, Command::Complete) => {
+            let candidates = store
+                .select(|state: &State| ex::complete(&state.doc, &state.command_line_state.value))
+                .await;
+            complete(store, &candidates, status_timeout).await;
+        }
+        (Page::CommandLine, Command::Up) => store.dispatch(Action::CommandLineHistoryUp).await,
+        (Page::CommandLine, Command::Down) => {
+            store.dispatch(Action::CommandLineHistoryDown).await;
+        }
+        (
+            Page::Search
+            | Page::ImportPrompt
+            | Page::ExportPrompt
+            | Page::SaveAsPrompt
+            | Page::CommandLine,
+            Command::DeleteChar,
+        ) => {
+            input(store, page, |value| {
+                value.pop();
+            })
+            .await;
+        }
+        // Bindings are checked against the page when the keymap is loaded, marks wait
+        // for their letter in `event_listener`
+        (
+            Page::Nav,
+            Command::Submit
+            | Command::DeleteChar
+            | Command::Complete
+            | Command::DeleteMark
+            | Command::SetMark
+            | Command::JumpToMark,
+        )
+        | (
+            Page::Search
+            | Page::ImportPrompt
+            | Page::ExportPrompt
+            | Page::SaveAsPrompt
+            | Page::CommandLine
+            | Page::Marks
+            | Page::Usages
+            | Page::Problems,
+            Command::Back
+            | Command::MoveDown
+            | Command::MoveUp
+            | Command::Confirm
+            | Command::Select
+            | Command::Top
+            | Command::Bottom
+            | Command::Undo
+            | Command::Redo
+            | Command::Edit
+            | Command::Save
+            | Command::SaveAs
+            | Command::Import
+            | Command::Export
+            | Command::Search
+            | Command::Quit
+            | Command::ForceQuit
+            | Command::OpenCommandLine
+            | Command::SetMark
+            | Command::JumpToMark
+            | Command::Marks
+            | Command::JumpBack
+            | Command::JumpForward
+            | Command::FindUsages
+            | Command::Problems,
+        )
+        | (
+            Page::Search
+            | Page::ImportPrompt
+            | Page::ExportPrompt
+            | Page::SaveAsPrompt
+            | Page::CommandLine
+            | Page::Usages
+            | Page::Problems,
+            Command::DeleteMark,
+        )
+        | (
+            Page::ImportPrompt | Page::ExportPrompt | Page::SaveAsPrompt,
+            Command::Down | Command::Up | Command::Complete,
+        )
+        | (Page::Search | Page::Marks | Page::Usages | Page::Problems, Command::Complete)
+        | (Page::Marks | Page::Usages | Page::Problems, Command::DeleteChar) => {}
+    }
+
+    Ok(ControlFlow::Continue(()))
+}
+
+/// Quits, unless there are unsaved changes outside of pick mode.
+async fn quit<R>(store: &Store<State, Action, R>, status_timeout: Duration) -> ControlFlow<()>
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let picking = store.select(|state: &State| state.pick.is_some()).await;
+    let undo_length = store.select(|state: &State| state.undo_stack.len()).await;
+
+    if picking || undo_length == 0 {
+        return ControlFlow::Break(());
+    }
+
+    store
+        .dispatch(Action::SetStatus {
+            message: StatusMessage::Warn(
+                "Unsaved changes, press ^q to quit without saving".to_owned(),
+            ),
+            timeout: Some(status_timeout),
+        })
+        .await;
+
+    ControlFlow::Continue(())
+}
+
+/// Runs a line typed into the command line.
+async fn execute<R, B>(
+    store: &Store<State, Action, R>,
+    lifecycle: &Mutex<Application<B>>,
+    line: &str,
+    status_timeout: Duration,
+) -> anyhow::Result<ControlFlow<()>>
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+    B: Backend + io::Write + Send + Sync + 'static,
+{
+    let command = match line.parse::<Ex>() {
+        Ok(command) => command,
+        Err(e) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Err(e.to_string()),
+                    timeout: None,
+                })
+                .await;
+            return Ok(ControlFlow::Continue(()));
+        }
+    };
+
+    let read_only = store.select(|state: &State| state.read_only).await;
+    match command {
+        Ex::Write(None) | Ex::WriteQuit(None) | Ex::Import(_) if read_only => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Warn(READ_ONLY_WARNING.to_owned()),
+                    timeout: Some(status_timeout),
+                })
+                .await;
+        }
+        Ex::Goto(path) => {
+            let exists = store
+                .select({
+                    let path = path.clone();
+                    move |state: &State| {
+                        path.parse::<ValuePointer>()
+                            .and_then(|pointer| pointer.get(&state.doc).map(|_| ()))
+                            .is_ok()
+                    }
+                })
+                .await;
+            if !exists {
+                store
+                    .dispatch(Action::SetStatus {
+                        message: StatusMessage::Warn(format!(
+                            "No value at {path}, moved to the closest one"
+                        )),
+                        timeout: Some(status_timeout),
+                    })
+                    .await;
+            }
+            store.dispatch(Action::NavGoto { path }).await;
+        }
+        Ex::Write(file_name) => {
+            write(store, file_name, status_timeout).await?;
+        }
+        Ex::WriteQuit(file_name) => {
+            if write(store, file_name, status_timeout).await? {
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        Ex::Quit => return Ok(quit(store, status_timeout).await),
+        Ex::ForceQuit => return Ok(ControlFlow::Break(())),
+        Ex::SetReadOnly(read_only) => {
+            store.dispatch(Action::SetReadOnly { read_only }).await;
+            let message = if read_only {
+                "Read only, changes to the document are refused"
+            } else {
+                "Changes to the document are allowed"
+            };
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Ok(message.to_owned()),
+                    timeout: Some(status_timeout),
+                })
+                .await;
+        }
+        Ex::Sort => store.dispatch(Action::SortCurrent).await,
+        Ex::Marks => open_marks(store).await,
+        Ex::Usages => find_usages(store, status_timeout).await,
+        Ex::Problems => open_problems(store, status_timeout).await,
+        Ex::Export(path) => {
+            store
+                .dispatch(Action::ExportPromptSetValue { value: path })
+                .await;
+            export(store, status_timeout).await?;
+        }
+        Ex::Import(path) => {
+            store
+                .dispatch(Action::ImportPromptSetValue { value: path })
+                .await;
+            import(store, lifecycle, status_timeout).await?;
+        }
+    }
+
+    Ok(ControlFlow::Continue(()))
+}
+
+/// Saves in place, or to `file_name` when given, returns whether the file was written.
+async fn write<R>(
+    store: &Store<State, Action, R>,
+    file_name: Option<String>,
+    status_timeout: Duration,
+) -> anyhow::Result<bool>
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    match file_name {
+        Some(file_name) => {
+            store
+                .dispatch(Action::SaveAsPromptSetValue { value: file_name })
+                .await;
+            save_as(store, status_timeout).await
+        }
+        None => save(store, status_timeout).await,
+    }
+}
+
+/// Sets or jumps to the mark named `letter`, as `command` asks, `None` when no letter was typed.
+async fn mark<R>(store: &Store<State, Action, R>, command: Command, letter: Option<char>)
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let status_timeout = store
+        .select(|state: &State| state.config.status_timeout())
+        .await;
+    // Any other key, like <Esc>, gives up on the mark
+    let Some(letter) = letter else {
+        return;
+    };
+    if !marks::is_mark(letter) {
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Warn(format!("Marks are named by a letter, not {letter}")),
+                timeout: Some(status_timeout),
+            })
+            .await;
+        return;
+    }
+
+    let external = store
+        .select(|state: &State| state.nav_state.current.file.is_some())
+        .await;
+    if command == Command::SetMark && external {
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Warn(
+                    "Marks can only be set in the document, not in referenced files".to_owned(),
+                ),
+                timeout: Some(status_timeout),
+            })
+            .await;
+        return;
+    }
+
+    if command == Command::SetMark {
+        let path = store
+            .select(|state: &State| {
+                state
+                    .highlighted_path()
+                    .unwrap_or_else(|| state.nav_state.current.path.clone())
+            })
+            .await;
+        store
+            .dispatch(Action::SetMark {
+                mark: letter,
+                path: path.clone(),
+            })
+            .await;
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Ok(format!("Marked {path} as '{letter}")),
+                timeout: Some(status_timeout),
+            })
+            .await;
+        save_marks(store).await;
+        return;
+    }
+
+    let path = store
+        .select(move |state: &State| state.marks.get(&letter).cloned())
+        .await;
+    if let Some(path) = path {
+        store.dispatch(Action::NavGoto { path }).await;
+    } else {
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Warn(format!("Mark '{letter} isn't set")),
+                timeout: Some(status_timeout),
+            })
+            .await;
+    }
+}
+
+/// Shows the list of marks, or tells there are none.
+async fn open_marks<R>(store: &Store<State, Action, R>)
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let empty = store.select(|state: &State| state.marks.is_empty()).await;
+    if empty {
+        let status_timeout = store
+            .select(|state: &State| state.config.status_timeout())
+            .await;
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Warn("No marks, set one with m<letter>".to_owned()),
+                timeout: Some(status_timeout),
+            })
+            .await;
+        return;
+    }
+
+    store
+        .dispatch(Action::SetCurrentPage { page: Page::Marks })
+        .await;
+}
+
+/// Goes to `path` chosen from a list, or picks it in pick mode, then leaves the list.
+async fn open_result<R>(
+    store: &Store<State, Action, R>,
+    path: Option<String>,
+    picking: bool,
+) -> ControlFlow<()>
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    if let Some(path) = path {
+        if picking {
+            store.dispatch(Action::Pick { path }).await;
+            return ControlFlow::Break(());
+        }
+
+        store.dispatch(Action::NavGoto { path }).await;
+    }
+
+    store
+        .dispatch(Action::SetCurrentPage { page: Page::Nav })
+        .await;
+
+    ControlFlow::Continue(())
+}
+
+/// Lists the broken `$ref`s of the document, or tells there are none.
+async fn open_problems<R>(store: &Store<State, Action, R>, status_timeout: Duration)
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let found = store
+        .select(|state: &State| !state.problems.is_empty())
+        .await;
+    if found {
+        store
+            .dispatch(Action::SetCurrentPage {
+                page: Page::Problems,
+            })
+            .await;
+    } else {
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Ok("No broken $refs".to_owned()),
+                timeout: Some(status_timeout),
+            })
+            .await;
+    }
+}
+
+/// Lists the `$ref`s pointing at the highlighted item, or tells there are none.
+async fn find_usages<R>(store: &Store<State, Action, R>, status_timeout: Duration)
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let external = store
+        .select(|state: &State| state.nav_state.current.file.is_some())
+        .await;
+    if external {
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Warn(
+                    "Usages are found in the document, not in referenced files".to_owned(),
+                ),
+                timeout: Some(status_timeout),
+            })
+            .await;
+        return;
+    }
+
+    let path = store
+        .select(|state: &State| {
+            state
+                .highlighted_path()
+                .unwrap_or_else(|| state.nav_state.current.path.clone())
+        })
+        .await;
+    store
+        .dispatch(Action::FindUsages { path: path.clone() })
+        .await;
+
+    let found = store
+        .select(|state: &State| !state.usages_state.results.is_empty())
+        .await;
+    if found {
+        store
+            .dispatch(Action::SetCurrentPage { page: Page::Usages })
+            .await;
+    } else {
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Warn(format!("Nothing references {path}")),
+                timeout: Some(status_timeout),
+            })
+            .await;
+    }
+}
+
+/// Writes the marks of the document to the state file, warning when that fails.
+async fn save_marks<R>(store: &Store<State, Action, R>)
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let result = store
+        .select(|state: &State| marks::save(&state.file_name, &state.marks))
+        .await;
+    if let Err(e) = result {
+        log::warn!("Unable to save marks: {e:?}");
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Warn(format!("Unable to save marks: {e}")),
+                timeout: None,
+            })
+            .await;
+    }
+}
+
+/// Follows a `$ref` into `path` of `file`, loading the file the first time.
+async fn follow<R>(
+    store: &Store<State, Action, R>,
+    file: &str,
+    path: String,
+    status_timeout: Duration,
+) where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let (base, file_name) = store
+        .select(|state: &State| (state.current_file().to_owned(), state.file_name.clone()))
+        .await;
+    let file = match reference::resolve(&base, file) {
+        Ok(file) => file,
+        Err(e) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Err(format!("Unable to follow reference: {e:#}")),
+                    timeout: None,
+                })
+                .await;
+            return;
+        }
+    };
+
+    // A reference back into the edited document
+    if reference::is_edited(&file, &file_name) {
+        store.dispatch(Action::NavFollow { file: None, path }).await;
+        return;
+    }
+
+    let loaded = store
+        .select({
+            let file = file.clone();
+            move |state: &State| state.external.contains_key(&file)
+        })
+        .await;
+    if !loaded {
+        match fetch_document(&file, None) {
+            Ok((doc, _)) => {
+                log::info!("Loaded referenced file {file}");
+                store
+                    .dispatch(Action::ExternalLoad {
+                        file: file.clone(),
+                        doc,
+                    })
+                    .await;
+            }
+            Err(e) => {
+                store
+                    .dispatch(Action::SetStatus {
+                        message: StatusMessage::Err(format!("Unable to load reference: {e:#}")),
+                        timeout: None,
+                    })
+                    .await;
+                return;
+            }
+        }
+    }
+
+    let exists = store
+        .select({
+            let (file, path) = (file.clone(), path.clone());
+            move |state: &State| {
+                state.external.get(&file).is_some_and(|doc| {
+                    path.parse::<ValuePointer>()
+                        .and_then(|pointer| pointer.get(doc).map(|_| ()))
+                        .is_ok()
+                })
+            }
+        })
+        .await;
+    if !exists {
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Warn(format!("No value at {path} in {file}")),
+                timeout: Some(status_timeout),
+            })
+            .await;
+        return;
+    }
+
+    store
+        .dispatch(Action::NavFollow {
+            file: Some(file),
+            path,
+        })
+        .await;
+}
+
+/// Completes the command line to what `candidates` share, listing them when there are several.
+async fn complete<R>(
+    store: &Store<State, Action, R>,
+    candidates: &[String],
+    status_timeout: Duration,
+) where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let current = store
+        .select(|state: &State| state.command_line_state.value.clone())
+        .await;
+    let prefix = ex::common_prefix(candidates);
+    if prefix.len() > current.len() {
+        store
+            .dispatch(Action::CommandLineSetValue { value: prefix })
+            .await;
+    }
+
+    if candidates.len() > 1 {
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Ok(candidates.join("  ")),
+                timeout: Some(status_timeout),
+            })
+            .await;
+    }
+}
+
+/// Changes the text typed into the prompt or search input of `page`.
+async fn input<R, F>(store: &Store<State, Action, R>, page: Page, change: F)
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+    F: FnOnce(&mut String) + Send,
+{
+    let mut value = store
+        .select(move |state: &State| match page {
+            Page::Search => state.search_state.value.clone(),
+            Page::ImportPrompt => state.import_prompt_state.value.clone(),
+            Page::ExportPrompt => state.export_prompt_state.value.clone(),
+            Page::SaveAsPrompt => state.save_as_prompt_state.value.clone(),
+            Page::CommandLine => state.command_line_state.value.clone(),
+            Page::Nav | Page::Marks | Page::Usages | Page::Problems => String::new(),
+        })
+        .await;
+    change(&mut value);
+
+    let action = match page {
+        Page::Search => Action::SearchSetValue { value },
+        Page::ImportPrompt => Action::ImportPromptSetValue { value },
+        Page::ExportPrompt => Action::ExportPromptSetValue { value },
+        Page::SaveAsPrompt => Action::SaveAsPromptSetValue { value },
+        Page::CommandLine => Action::CommandLineSetValue { value },
+        Page::Nav | Page::Marks | Page::Usages | Page::Problems => return,
+    };
+    store.dispatch(action).await;
+}
+
+/// Selects the highlighted item, when it has children to show.
+async fn select<R>(store: &Store<State, Action, R>, status_timeout: Duration) -> anyhow::Result<()>
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let reference = store
+        .select(|state: &State| state.highlighted_reference().map(str::to_owned))
+        .await;
+    if let Some(reference) = reference {
+        let (file, path) = reference::split(&reference);
+        if !file.is_empty() {
+            follow(store, file, path, status_timeout).await;
+            return Ok(());
+        }
+
+        let problem = store
+            .select(|state: &State| {
+                // Only the document itself is checked
+                let path = state
+                    .highlighted_path()
+                    .filter(|_| state.nav_state.current.file.is_none())?;
+                state
+                    .problems
+                    .iter()
+                    .find(|problem| problem.path == path)
+                    .map(ToString::to_string)
+            })
+            .await;
+        if let Some(problem) = problem {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Warn(problem),
+                    timeout: Some(status_timeout),
+                })
+                .await;
+            return Ok(());
+        }
+    }
+
+    let children = store
+        .select(|state: &State| {
+            state
+                .nav_state
+                .current
+                .path
+                .parse::<ValuePointer>()
+                .ok()
+                .and_then(|pointer| pointer.get(state.current_doc()).ok())
+                .and_then(|value| match value {
+                    &Value::Array(ref arr) => arr.get(state.nav_state.current.selected),
+                    &Value::Object(ref obj) => obj
+                        .get_index(state.nav_state.current.selected)
+                        .map(|(_, v)| v),
+                    &Value::Null
+                    | &Value::Bool(_)
+                    | &Value::String(_)
+                    | &Value::Number(_)
+                    | &Value::Bytes(_) => None,
+                })
+                .map_or(0, |child| match child {
+                    &Value::Array(ref arr) => arr.len(),
+                    &Value::Object(ref obj) => obj.len(),
+                    &Value::Null
+                    | &Value::Bool(_)
+                    | &Value::String(_)
+                    | &Value::Number(_)
+                    | &Value::Bytes(_) => 0,
+                })
+        })
+        .await;
+
+    if children > 0 {
+        store.dispatch(Action::NavSelect).await;
+    } else {
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Warn(
+                    "No children to select, use ^e to edit this value".to_owned(),
+                ),
+                timeout: Some(status_timeout),
+            })
+            .await;
+    }
+    Ok(())
+}
+
+/// Replaces the highlighted value with the result of editing it in `$EDITOR`.
+async fn edit<R, B>(
+    store: &Store<State, Action, R>,
+    lifecycle: &Mutex<Application<B>>,
+    status_timeout: Duration,
+) -> anyhow::Result<()>
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+    B: Backend + io::Write + Send + Sync + 'static,
+{
+    let existing_value = store
+        .select(|state: &State| {
+            state
+                .nav_state
+                .current
+                .path
+                .parse::<ValuePointer>()
+                .ok()
+                .and_then(|pointer| pointer.get(&state.doc).ok())
+                .and_then(|value| match value {
+                    &Value::Array(ref arr) => arr.get(state.nav_state.current.selected),
+                    &Value::Object(ref obj) => obj
+                        .get_index(state.nav_state.current.selected)
+                        .map(|(_, v)| v),
+                    &Value::Null
+                    | &Value::Bool(_)
+                    | &Value::String(_)
+                    | &Value::Number(_)
+                    | &Value::Bytes(_) => None,
+                })
+                .cloned()
+                .unwrap_or(Value::Null)
+        })
+        .await;
+
+    {
+        let mut lifecycle = lifecycle
+            .lock()
+            .map_err(|e| anyhow!("Unable to get lifecycle lock: {e}"))?;
+        lifecycle.suspend()?;
+    };
+
+    let (file_name, format) = store
+        .select(|state: &State| (state.file_name.clone(), state.format().fragment()))
+        .await;
+    let new_value = editor(&existing_value, &file_name, format);
+
+    {
+        let mut lifecycle = lifecycle
+            .lock()
+            .map_err(|e| anyhow!("Unable to get lifecycle lock: {e}"))?;
+        lifecycle.resume()?;
+    };
+
+    match new_value {
+        Ok(new_value) => {
+            store
+                .dispatch(Action::DocumentReplaceCurrent { value: new_value })
+                .await;
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Ok("Successfully edited value".to_owned()),
+                    timeout: Some(status_timeout),
+                })
+                .await;
+        }
+        Err(e) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Err(format!("Unable to edit value: {e}")),
+                    timeout: None,
+                })
+                .await;
+        }
+    }
+    Ok(())
+}
+
+/// Saves the document in place, or asks where to when it came from standard input.
+/// Returns whether the file was written.
+async fn save<R>(store: &Store<State, Action, R>, status_timeout: Duration) -> anyhow::Result<bool>
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let file_name = store.select(|state: &State| state.file_name.clone()).await;
+
+    // There is nothing to save in place, ask where to put it instead
+    if file_name == STDIN {
+        store
+            .dispatch(Action::SaveAsPromptSetValue {
+                value: String::new(),
+            })
+            .await;
+        store
+            .dispatch(Action::SetCurrentPage {
+                page: Page::SaveAsPrompt,
+            })
+            .await;
+        return Ok(false);
+    }
+
+    let doc = store.select(|state: &State| state.doc.clone()).await;
+    let persisted = store.select(|state: &State| state.persisted.clone()).await;
+
+    let format = store.select(State::format).await;
+    if warn_comments(store, &doc, persisted.as_ref(), format, "press ^s again").await {
+        return Ok(false);
+    }
+
+    let backup = store.select(|state: &State| state.config.backup()).await;
+    let result = save_doc(
+        &file_name,
+        &doc,
+        format,
+        persisted.as_ref().and_then(|p| p.compression),
+        persisted.as_ref().and_then(|p| p.source.as_ref()),
+        &persisted
+            .as_ref()
+            .map(|p| p.datetimes.clone())
+            .unwrap_or_default(),
+        persisted.as_ref().map(|p| p.fingerprint),
+        backup,
+    );
+
+    let saved = result.is_ok();
+    match result {
+        Ok(persisted) => {
+            store
+                .dispatch(Action::SetFile {
+                    file_name,
+                    persisted,
+                })
+                .await;
+            store.dispatch(Action::Snapshot).await;
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Ok("Successfully saved file".to_owned()),
+                    timeout: Some(status_timeout),
+                })
+                .await;
+        }
+        Err(e) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Err(format!(
+                        "Unable to save file: {e}, use S to save as another file"
+                    )),
+                    timeout: None,
+                })
+                .await;
+        }
+    }
+
+    Ok(saved)
+}
+
+/// Warns that saving `doc` drops the comments of the file the first time, returns whether
+/// it did. `again` tells how to save anyway.
+async fn warn_comments<R>(
+    store: &Store<State, Action, R>,
+    doc: &Value,
+    persisted: Option<&Persisted>,
+    format: Format,
+    again: &str,
+) -> bool
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    if !persisted.is_some_and(|persisted| drops_comments(doc, persisted, format)) {
+        return false;
+    }
+
+    store.dispatch(Action::DiscardComments).await;
+    store
+        .dispatch(Action::SetStatus {
+            message: StatusMessage::Warn(format!(
+                "Saving drops the comments in this file, {again} to save anyway"
+            )),
+            timeout: None,
+        })
+        .await;
+
+    true
+}
+
+/// Replaces the highlighted value with the file in the import prompt, after editing it.
+async fn import<R, B>(
+    store: &Store<State, Action, R>,
+    lifecycle: &Mutex<Application<B>>,
+    status_timeout: Duration,
+) -> anyhow::Result<()>
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+    B: Backend + io::Write + Send + Sync + 'static,
+{
+    let current_path = store
+        .select(|state: &State| state.import_prompt_state.value.clone())
+        .await;
+
+    let existing_value = match fs::read(&current_path) {
+        Ok(existing_value) => existing_value,
+        Err(e) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Err(format!("Unable to read file: {e}")),
+                    timeout: None,
+                })
+                .await;
+            store
+                .dispatch(Action::SetCurrentPage { page: Page::Nav })
+                .await;
+            return Ok(());
+        }
+    };
+
+    let existing_value = match Compression::detect(&current_path, &existing_value) {
+        Some(compression) => compression.decompress(&existing_value),
+        None => Ok(existing_value),
+    };
+    let existing_value = match existing_value
+        .and_then(|data| Format::detect(Compression::strip(&current_path), &data).decode(&data))
+    {
+        Ok(existing_value) => existing_value,
+        Err(e) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Err(format!("Unable to parse file: {e}")),
+                    timeout: None,
+                })
+                .await;
+            store
+                .dispatch(Action::SetCurrentPage { page: Page::Nav })
+                .await;
+            return Ok(());
+        }
+    };
+
+    {
+        let mut lifecycle = lifecycle
+            .lock()
+            .map_err(|e| anyhow!("Unable to get lifecycle lock: {e}"))?;
+        lifecycle.suspend()?;
+    };
+
+    let (file_name, format) = store
+        .select(|state: &State| (state.file_name.clone(), state.format().fragment()))
+        .await;
+    let new_value = editor(&existing_value, &file_name, format);
+
+    {
+        let mut lifecycle = lifecycle
+            .lock()
+            .map_err(|e| anyhow!("Unable to get lifecycle lock: {e}"))?;
+        lifecycle.resume()?;
+    };
+
+    match new_value {
+        Ok(new_value) => {
+            store
+                .dispatch(Action::DocumentReplaceCurrent { value: new_value })
+                .await;
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Ok("Successfully edited value".to_owned()),
+                    timeout: Some(status_timeout),
+                })
+                .await;
+        }
+        Err(e) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Err(format!("Unable to edit value: {e}")),
+                    timeout: None,
+                })
+                .await;
+        }
+    }
+
+    store
+        .dispatch(Action::SetCurrentPage { page: Page::Nav })
+        .await;
+    Ok(())
+}
+
+/// Writes the highlighted value to the file in the export prompt.
+async fn export<R>(store: &Store<State, Action, R>, status_timeout: Duration) -> anyhow::Result<()>
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let existing_value = store
+        .select(|state: &State| {
+            state
+                .nav_state
+                .current
+                .path
+                .parse::<ValuePointer>()
+                .ok()
+                .and_then(|pointer| pointer.get(state.current_doc()).ok())
+                .and_then(|value| match value {
+                    &Value::Array(ref arr) => arr.get(state.nav_state.current.selected),
+                    &Value::Object(ref obj) => obj
+                        .get_index(state.nav_state.current.selected)
+                        .map(|(_, v)| v),
+                    &Value::Null
+                    | &Value::Bool(_)
+                    | &Value::String(_)
+                    | &Value::Number(_)
+                    | &Value::Bytes(_) => None,
+                })
+                .cloned()
+                .unwrap_or(Value::Null)
+        })
+        .await;
+
+    let current_path = store
+        .select(|state: &State| state.export_prompt_state.value.clone())
+        .await;
+
+    let format = store.select(State::format).await;
+    let format = Format::from_path(Compression::strip(&current_path)).unwrap_or(format.fragment());
+    let existing_value = format.encode(&existing_value);
+    let existing_value = match Compression::from_path(&current_path) {
+        Some(compression) => existing_value.and_then(|data| compression.compress(&data)),
+        None => existing_value,
+    };
+    let existing_value = match existing_value {
+        Ok(existing_value) => existing_value,
+        Err(e) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Err(format!("Unable to serialize value: {e}")),
+                    timeout: None,
+                })
+                .await;
+            store
+                .dispatch(Action::SetCurrentPage { page: Page::Nav })
+                .await;
+            return Ok(());
+        }
+    };
+
+    let mut file = match File::create(&current_path) {
+        Ok(file) => file,
+        Err(e) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Err(format!("Unable to create file: {e}")),
+                    timeout: None,
+                })
+                .await;
+            store
+                .dispatch(Action::SetCurrentPage { page: Page::Nav })
+                .await;
+            return Ok(());
+        }
+    };
+
+    match file.write_all(&existing_value) {
+        Ok(()) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Ok("Successfully exported value".to_owned()),
+                    timeout: Some(status_timeout),
+                })
+                .await;
+        }
+        Err(e) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Err(format!("Unable to write file: {e}")),
+                    timeout: None,
+                })
+                .await;
+        }
+    }
+
+    store
+        .dispatch(Action::SetCurrentPage { page: Page::Nav })
+        .await;
+    Ok(())
+}
+
+/// Saves the document to the file in the save as prompt, returns whether it was written.
+async fn save_as<R>(
+    store: &Store<State, Action, R>,
+    status_timeout: Duration,
+) -> anyhow::Result<bool>
+where
+    R: Reducer<State, Action> + Send + Sync + 'static,
+{
+    let file_name = store
+        .select(|state: &State| state.save_as_prompt_state.value.clone())
+        .await;
+    let doc = store.select(|state: &State| state.doc.clone()).await;
+    let persisted = store.select(|state: &State| state.persisted.clone()).await;
+    let backup = store.select(|state: &State| state.config.backup()).await;
+    let source = persisted.as_ref().and_then(|p| p.source.as_ref());
+    let format = store.select(State::format).await;
+    let format = format.for_path(Compression::strip(&file_name));
+    let compression = Compression::from_path(&file_name);
+
+    // Another file is only replaced when saving to it a second time
+    let current = store.select(|state: &State| state.file_name.clone()).await;
+    let confirmed = store
+        .select(|state: &State| state.save_as_prompt_state.overwrite.clone())
+        .await;
+    let replaces = fs::canonicalize(&file_name)
+        .is_ok_and(|target| fs::canonicalize(&current).ok() != Some(target));
+    if replaces && confirmed.as_ref() != Some(&file_name) {
+        store
+            .dispatch(Action::ConfirmOverwrite {
+                file_name: file_name.clone(),
+            })
+            .await;
+        store
+            .dispatch(Action::SetStatus {
+                message: StatusMessage::Warn(format!(
+                    "{file_name} already exists, save to it again to overwrite it"
+                )),
+                timeout: None,
+            })
+            .await;
+        return Ok(false);
+    }
+
+    if warn_comments(store, &doc, persisted.as_ref(), format, "save again").await {
+        return Ok(false);
+    }
+
+    let datetimes = persisted
+        .as_ref()
+        .map(|p| p.datetimes.clone())
+        .unwrap_or_default();
+
+    let result = save_doc(
+        &file_name,
+        &doc,
+        format,
+        compression,
+        source,
+        &datetimes,
+        None,
+        backup,
+    );
+    let saved = result.is_ok();
+    match result {
+        Ok(persisted) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Ok(format!("Successfully saved file to {file_name}")),
+                    timeout: Some(status_timeout),
+                })
+                .await;
+            store
+                .dispatch(Action::SetFile {
+                    file_name,
+                    persisted,
+                })
+                .await;
+            store.dispatch(Action::Snapshot).await;
+        }
+        Err(e) => {
+            store
+                .dispatch(Action::SetStatus {
+                    message: StatusMessage::Err(format!("Unable to save file: {e}")),
+                    timeout: None,
+                })
+                .await;
+        }
+    }
+
+    store
+        .dispatch(Action::SetCurrentPage { page: Page::Nav })
+        .await;
+    Ok(saved)
+}
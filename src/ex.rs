@@ -0,0 +1,275 @@
+//! Commands typed into the `:` command line of the navigation view, like `:goto #/info`
+//! or `:w other.json`.
+
+use core::str::FromStr;
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    pointer::{escape, ValuePointer},
+    state::ROOT_PATH,
+    value::Value,
+};
+
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ex {
+    /// Navigate to the value at a pointer.
+    Goto(String),
+
+    /// Save in place, or to another file.
+    Write(Option<String>),
+
+    /// Save, then quit.
+    WriteQuit(Option<String>),
+
+    /// Quit, unless there are unsaved changes.
+    Quit,
+
+    /// Quit without saving.
+    ForceQuit,
+
+    /// Turn read only mode on or off.
+    SetReadOnly(bool),
+
+    /// Sort the entries of the current object or array.
+    Sort,
+
+    /// Show the list of marks.
+    Marks,
+
+    /// List the `$ref`s pointing at the highlighted item.
+    Usages,
+
+    /// List the broken `$ref`s of the document.
+    Problems,
+
+    /// Write the highlighted value to a file.
+    Export(String),
+
+    /// Replace the highlighted value with a file.
+    Import(String),
+}
+
+/// Command names offered by completion.
+const NAMES: [&str; 15] = [
+    "export", "goto", "import", "marks", "problems", "q", "q!", "quit", "quit!", "set", "sort",
+    "usages", "w", "wq", "write",
+];
+
+/// Options of `:set`.
+const SETTINGS: [&str; 4] = ["noreadonly", "noro", "readonly", "ro"];
+
+impl FromStr for Ex {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let line = s.trim();
+        let (name, argument) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, None), |(name, argument)| {
+                (name, Some(argument.trim()))
+            });
+        let required = |usage: &str| {
+            argument
+                .map(str::to_owned)
+                .ok_or_else(|| anyhow!("Missing argument, usage: {usage}"))
+        };
+
+        let command = match name {
+            "goto" => Self::Goto(required(":goto <pointer>")?),
+            "w" | "write" => Self::Write(argument.map(str::to_owned)),
+            "wq" => Self::WriteQuit(argument.map(str::to_owned)),
+            "q" | "quit" => Self::Quit,
+            "q!" | "quit!" => Self::ForceQuit,
+            "set" => match required(":set readonly|noreadonly")?.as_str() {
+                "readonly" | "ro" => Self::SetReadOnly(true),
+                "noreadonly" | "noro" => Self::SetReadOnly(false),
+                option => bail!("Unknown option {option}, expected readonly or noreadonly"),
+            },
+            "sort" => Self::Sort,
+            "marks" => Self::Marks,
+            "usages" => Self::Usages,
+            "problems" => Self::Problems,
+            "export" => Self::Export(required(":export <path>")?),
+            "import" => Self::Import(required(":import <path>")?),
+            _ => bail!("Unknown command :{name}"),
+        };
+
+        let takes_argument = matches!(
+            command,
+            Self::Goto(_)
+                | Self::Write(_)
+                | Self::WriteQuit(_)
+                | Self::SetReadOnly(_)
+                | Self::Export(_)
+                | Self::Import(_)
+        );
+        if argument.is_some() && !takes_argument {
+            bail!(":{name} doesn't take an argument");
+        }
+
+        Ok(command)
+    }
+}
+
+/// Lines `line` can be completed to, command names for the first word and
+/// pointers into `doc` or options after it.
+pub fn complete(doc: &Value, line: &str) -> Vec<String> {
+    let Some((name, argument)) = line.split_once(' ') else {
+        return NAMES
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(line))
+            .map(str::to_owned)
+            .collect();
+    };
+
+    let candidates = match name {
+        "goto" => complete_pointer(doc, argument.trim_start()),
+        "set" => SETTINGS
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(argument.trim_start()))
+            .map(str::to_owned)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    candidates
+        .into_iter()
+        .map(|candidate| format!("{name} {candidate}"))
+        .collect()
+}
+
+/// Pointers to the children of the value `partial` is in, whose last token starts like it.
+fn complete_pointer(doc: &Value, partial: &str) -> Vec<String> {
+    let (parent, prefix) = partial.rsplit_once('/').unwrap_or((ROOT_PATH, ""));
+    let parent = if parent.is_empty() { ROOT_PATH } else { parent };
+
+    let Some(value) = parent
+        .parse::<ValuePointer>()
+        .ok()
+        .and_then(|pointer| pointer.get(doc).ok())
+    else {
+        return Vec::new();
+    };
+
+    let tokens: Vec<String> = match value {
+        &Value::Object(ref map) => map.keys().map(|key| escape(key)).collect(),
+        &Value::Array(ref array) => (0..array.len()).map(|index| index.to_string()).collect(),
+        &Value::Null
+        | &Value::Bool(_)
+        | &Value::String(_)
+        | &Value::Number(_)
+        | &Value::Bytes(_) => Vec::new(),
+    };
+
+    tokens
+        .into_iter()
+        .filter(|token| token.starts_with(prefix))
+        .map(|token| format!("{parent}/{token}"))
+        .collect()
+}
+
+/// The longest start all of `candidates` share.
+pub fn common_prefix(candidates: &[String]) -> String {
+    let Some((first, rest)) = candidates.split_first() else {
+        return String::new();
+    };
+
+    let mut prefix = first.clone();
+    for candidate in rest {
+        let shared = prefix
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|&((_, a), b)| a != b)
+            .map_or_else(
+                || prefix.len().min(candidate.len()),
+                |((index, _), _)| index,
+            );
+        prefix.truncate(shared);
+    }
+
+    prefix
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::bail;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let cases = [
+            ("goto #/info", Ex::Goto("#/info".to_owned())),
+            ("w", Ex::Write(None)),
+            ("w other.json", Ex::Write(Some("other.json".to_owned()))),
+            ("  wq  ", Ex::WriteQuit(None)),
+            ("q!", Ex::ForceQuit),
+            ("set readonly", Ex::SetReadOnly(true)),
+            ("set noro", Ex::SetReadOnly(false)),
+            ("sort", Ex::Sort),
+            ("marks", Ex::Marks),
+            ("usages", Ex::Usages),
+            ("problems", Ex::Problems),
+            ("export out.yaml", Ex::Export("out.yaml".to_owned())),
+            ("import in.json", Ex::Import("in.json".to_owned())),
+        ];
+
+        for (line, expected) in cases {
+            let command = line.parse::<Ex>()?;
+            if command != expected {
+                bail!("Expected {:?} for {:?}, got {:?}", expected, line, command);
+            }
+        }
+
+        for line in ["goto", "set fast", "sort keys", "edit", ""] {
+            if let Ok(command) = line.parse::<Ex>() {
+                bail!("Expected {:?} to be refused, got {:?}", line, command);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_complete() -> anyhow::Result<()> {
+        let doc: Value =
+            serde_yaml::from_str("info:\n  title: x\n  tags: [a]\npaths:\n  /pets: {}\n")?;
+
+        let cases = [
+            ("so", vec!["sort"]),
+            ("m", vec!["marks"]),
+            ("qu", vec!["quit", "quit!"]),
+            ("goto ", vec!["goto #/info", "goto #/paths"]),
+            ("goto #/i", vec!["goto #/info"]),
+            (
+                "goto #/info/t",
+                vec!["goto #/info/title", "goto #/info/tags"],
+            ),
+            ("goto #/info/tags/", vec!["goto #/info/tags/0"]),
+            ("goto #/paths/~1", vec!["goto #/paths/~1pets"]),
+            ("set r", vec!["set readonly", "set ro"]),
+            ("w ", vec![]),
+        ];
+
+        for (line, expected) in cases {
+            let candidates = complete(&doc, line);
+            if candidates != expected {
+                bail!(
+                    "Expected {:?} for {:?}, got {:?}",
+                    expected,
+                    line,
+                    candidates
+                );
+            }
+        }
+
+        let prefix = common_prefix(&complete(&doc, "goto #/info/t"));
+        if prefix != "goto #/info/t" {
+            bail!("Expected {:?}, got {:?}", "goto #/info/t", prefix);
+        }
+
+        Ok(())
+    }
+}
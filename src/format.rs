@@ -0,0 +1,897 @@
+//! Every document format rover can read and write, so supporting a new one
+//! only means teaching this module about it.
+
+use core::{
+    fmt,
+    str::{self, FromStr},
+};
+use std::{collections::HashSet, path::Path};
+
+use anyhow::{anyhow, bail, Context as _};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::Deserialize as _;
+
+use crate::{pointer::escape, source::yaml_has_comments, state::ROOT_PATH, value::Value};
+
+/// Pointers of the values that were TOML datetimes when a document was read. They are
+/// read as strings, and only these are written back as datetimes.
+pub type Datetimes = HashSet<String>;
+
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    ///
+    Json,
+
+    ///
+    Yaml,
+
+    ///
+    Toml,
+
+    /// Several YAML documents separated by `---`, loaded as a list with one
+    /// entry per document.
+    YamlStream,
+
+    /// One JSON value per line, loaded as an array of records.
+    JsonLines,
+
+    /// JSON with comments, trailing commas and the rest of the JSON5 extensions.
+    Json5,
+
+    ///
+    MessagePack,
+
+    ///
+    Cbor,
+}
+
+impl Format {
+    /// Every format that can be asked for by name, in the order they're listed
+    /// to users. A YAML stream is asked for as YAML.
+    pub const ALL: [Self; 7] = [
+        Self::Json,
+        Self::Yaml,
+        Self::Toml,
+        Self::JsonLines,
+        Self::Json5,
+        Self::MessagePack,
+        Self::Cbor,
+    ];
+
+    /// Extension written when a file name is made up for a document.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Yaml | Self::YamlStream => "yaml",
+            Self::Toml => "toml",
+            Self::JsonLines => "jsonl",
+            Self::Json5 => "json5",
+            Self::MessagePack => "msgpack",
+            Self::Cbor => "cbor",
+        }
+    }
+
+    /// Whether documents in this format aren't text, so can't be shown or
+    /// edited as they are.
+    pub const fn is_binary(self) -> bool {
+        matches!(self, Self::MessagePack | Self::Cbor)
+    }
+
+    /// Format used for values picked out of a document, like the preview or
+    /// a value opened in the editor.
+    pub const fn fragment(self) -> Self {
+        match self {
+            Self::JsonLines | Self::MessagePack | Self::Cbor => Self::Json,
+            Self::YamlStream => Self::Yaml,
+            Self::Json | Self::Yaml | Self::Toml | Self::Json5 => self,
+        }
+    }
+
+    ///
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            "jsonl" | "ndjson" => Some(Self::JsonLines),
+            "json5" | "jsonc" => Some(Self::Json5),
+            "msgpack" | "mpk" => Some(Self::MessagePack),
+            "cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Format named by the extensions of `path`, looking past unknown ones so
+    /// `openapi.json.tmpl` is still read as JSON.
+    pub fn from_path<P: AsRef<Path> + ?Sized>(path: &P) -> Option<Self> {
+        let file_name = path.as_ref().file_name()?.to_string_lossy();
+
+        let (_, extensions) = file_name.split_once('.')?;
+        extensions.rsplit('.').find_map(Self::from_extension)
+    }
+
+    /// Guesses the format of a document from its contents.
+    pub fn sniff(data: &[u8]) -> Self {
+        let Ok(text) = str::from_utf8(data) else {
+            return sniff_binary(data);
+        };
+
+        let looks_like_json = blank_comments(text).trim_start().starts_with(['{', '[']);
+
+        if looks_like_json {
+            if serde_json::from_str::<serde_json::Value>(text).is_ok() {
+                return Self::Json;
+            }
+
+            if parse_json_lines(text).is_ok() {
+                return Self::JsonLines;
+            }
+
+            if json5::from_str::<Value>(text).is_ok() {
+                return Self::Json5;
+            }
+        }
+
+        // Most YAML isn't valid TOML, and the TOML that is valid YAML is just a string
+        if toml::from_str::<toml::Table>(text).is_ok_and(|table| !table.is_empty()) {
+            return Self::Toml;
+        }
+
+        Self::Yaml
+    }
+
+    /// Format of the document in `path`, falling back to its contents when the
+    /// file name doesn't tell.
+    pub fn detect<P: AsRef<Path> + ?Sized>(path: &P, data: &[u8]) -> Self {
+        Self::from_path(path)
+            .unwrap_or_else(|| Self::sniff(data))
+            .narrow(data)
+    }
+
+    /// Picks the flavour of this format `text` is written in, a YAML file with
+    /// more than one document is read as a stream and a JSON file with comments
+    /// as JSON5.
+    pub fn narrow(self, data: &[u8]) -> Self {
+        let Ok(text) = str::from_utf8(data) else {
+            return self;
+        };
+
+        match self {
+            Self::Yaml if serde_yaml::Deserializer::from_str(text).nth(1).is_some() => {
+                Self::YamlStream
+            }
+            Self::Json
+                if serde_json::from_str::<serde_json::Value>(text).is_err()
+                    && json5::from_str::<Value>(text).is_ok() =>
+            {
+                Self::Json5
+            }
+            Self::Json
+            | Self::Yaml
+            | Self::YamlStream
+            | Self::Toml
+            | Self::JsonLines
+            | Self::Json5
+            | Self::MessagePack
+            | Self::Cbor => self,
+        }
+    }
+
+    /// Format to save the document in when it's written to `path`.
+    pub fn for_path<P: AsRef<Path> + ?Sized>(self, path: &P) -> Self {
+        match Self::from_path(path) {
+            // Still YAML, just more than one document of it
+            Some(Self::Yaml) if self == Self::YamlStream => self,
+            // Keep the comments of a `.json` file that has them
+            Some(Self::Json) if self == Self::Json5 => self,
+            Some(format) => format,
+            None => self,
+        }
+    }
+
+    /// Whether `text` has comments, trailing ones included.
+    pub fn has_comments(self, text: &str) -> bool {
+        match self {
+            Self::Json | Self::JsonLines | Self::MessagePack | Self::Cbor => false,
+            Self::Json5 => blank_comments(text) != text,
+            Self::Yaml | Self::YamlStream => yaml_has_comments(text),
+            Self::Toml => toml_has_comments(text),
+        }
+    }
+
+    ///
+    pub fn decode(self, data: &[u8]) -> anyhow::Result<Value> {
+        match self {
+            Self::MessagePack => Ok(rmp_serde::from_slice(data)?),
+            Self::Cbor => Ok(ciborium::from_reader(data)?),
+            Self::Json
+            | Self::Yaml
+            | Self::YamlStream
+            | Self::Toml
+            | Self::JsonLines
+            | Self::Json5 => self.parse(str::from_utf8(data)?),
+        }
+    }
+
+    /// Decodes a whole document, along with its TOML datetimes.
+    pub fn decode_document(self, data: &[u8]) -> anyhow::Result<(Value, Datetimes)> {
+        if self == Self::Toml {
+            return parse_toml(str::from_utf8(data)?);
+        }
+
+        Ok((self.decode(data)?, Datetimes::new()))
+    }
+
+    /// Encodes a whole document, writing the strings at `datetimes` as TOML datetimes.
+    pub fn encode_document(self, value: &Value, datetimes: &Datetimes) -> anyhow::Result<Vec<u8>> {
+        if self == Self::Toml {
+            return Ok(serialize_toml(value, datetimes)?.into_bytes());
+        }
+
+        self.encode(value)
+    }
+
+    ///
+    pub fn encode(self, value: &Value) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::MessagePack => Ok(rmp_serde::to_vec_named(value)?),
+            Self::Cbor => {
+                let mut data = Vec::new();
+                ciborium::into_writer(value, &mut data)?;
+                Ok(data)
+            }
+            Self::Json
+            | Self::Yaml
+            | Self::YamlStream
+            | Self::Toml
+            | Self::JsonLines
+            | Self::Json5 => Ok(self.serialize(value)?.into_bytes()),
+        }
+    }
+
+    ///
+    pub fn parse(self, text: &str) -> anyhow::Result<Value> {
+        match self {
+            Self::MessagePack | Self::Cbor => bail!("{self} documents can't be read from text"),
+            Self::Json => Ok(serde_json::from_str(text)?),
+            Self::Yaml => Ok(serde_yaml::from_str(text)?),
+            Self::YamlStream => serde_yaml::Deserializer::from_str(text)
+                .map(|document| Ok(Value::deserialize(document)?))
+                .collect::<anyhow::Result<_>>()
+                .map(Value::Array),
+            Self::Toml => Ok(parse_toml(text)?.0),
+            Self::JsonLines => parse_json_lines(text),
+            Self::Json5 => Ok(json5::from_str(text)?),
+        }
+    }
+
+    ///
+    pub fn serialize(self, value: &Value) -> anyhow::Result<String> {
+        match self {
+            Self::MessagePack | Self::Cbor => bail!("{self} documents can't be written as text"),
+            Self::Json | Self::Json5 => Ok(serde_json::to_string_pretty(value)?),
+            Self::Yaml => Ok(serde_yaml::to_string(value)?),
+            Self::Toml => serialize_toml(value, &Datetimes::new()),
+            Self::YamlStream => match value {
+                &Value::Array(ref documents) => {
+                    let documents = documents
+                        .iter()
+                        .map(serde_yaml::to_string)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(documents.join("---\n"))
+                }
+                &Value::Null
+                | &Value::Bool(_)
+                | &Value::String(_)
+                | &Value::Number(_)
+                | &Value::Object(_)
+                | &Value::Bytes(_) => bail!("A YAML stream has to be a list of documents"),
+            },
+            Self::JsonLines => match value {
+                &Value::Array(ref records) => {
+                    let mut out = String::new();
+                    for record in records {
+                        out.push_str(&serde_json::to_string(record)?);
+                        out.push('\n');
+                    }
+                    Ok(out)
+                }
+                &Value::Null
+                | &Value::Bool(_)
+                | &Value::String(_)
+                | &Value::Number(_)
+                | &Value::Object(_)
+                | &Value::Bytes(_) => {
+                    bail!("A JSON Lines document has to be a list of records")
+                }
+            },
+        }
+    }
+}
+
+/// Tells the binary formats apart by which of them reads all of `data`.
+fn sniff_binary(data: &[u8]) -> Format {
+    // Self-described CBOR
+    if data.starts_with(&[0xd9, 0xd9, 0xf7]) {
+        return Format::Cbor;
+    }
+
+    let mut rest = data;
+    if rmp_serde::from_read::<_, Value>(&mut rest).is_ok() && rest.is_empty() {
+        return Format::MessagePack;
+    }
+
+    let mut rest = data;
+    if ciborium::from_reader::<Value, _>(&mut rest).is_ok() && rest.is_empty() {
+        return Format::Cbor;
+    }
+
+    Format::MessagePack
+}
+
+/// Replaces the comments in JSON5 `text` with spaces, keeping every other
+/// character where it was so positions in the result match the original.
+pub fn blank_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut quote = None;
+
+    while let Some(ch) = chars.next() {
+        if let Some(open) = quote {
+            out.push(ch);
+            match ch {
+                '\\' => out.extend(chars.next()),
+                _ if ch == open => quote = None,
+                _ => {}
+            }
+            continue;
+        }
+
+        match (ch, chars.peek()) {
+            ('"' | '\'', _) => {
+                quote = Some(ch);
+                out.push(ch);
+            }
+            ('/', Some(&'/')) => {
+                out.push(' ');
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    chars.next();
+                    out.extend(core::iter::repeat_n(' ', next.len_utf8()));
+                }
+            }
+            ('/', Some(&'*')) => {
+                chars.next();
+                out.push_str("  ");
+
+                let mut last = ' ';
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        out.push('\n');
+                    } else {
+                        out.extend(core::iter::repeat_n(' ', next.len_utf8()));
+                    }
+
+                    if last == '*' && next == '/' {
+                        break;
+                    }
+                    last = next;
+                }
+            }
+            _ => out.push(ch),
+        }
+    }
+
+    out
+}
+
+/// Reads every non-blank line as a record.
+fn parse_json_lines(text: &str) -> anyhow::Result<Value> {
+    text.lines()
+        .enumerate()
+        .filter(|&(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Invalid record on line {}", idx.saturating_add(1)))
+        })
+        .collect::<anyhow::Result<_>>()
+        .map(Value::Array)
+}
+
+/// Reads a TOML document, or a single inline value such as `"1.0"` or `[1, 2]`.
+fn parse_toml(text: &str) -> anyhow::Result<(Value, Datetimes)> {
+    let table = match toml::from_str::<toml::Table>(text) {
+        Ok(table) => table,
+        Err(err) => {
+            let Ok(mut inline) = toml::from_str::<toml::Table>(&format!("value = {}", text.trim()))
+            else {
+                return Err(err.into());
+            };
+
+            let Some(value) = inline.remove("value") else {
+                return Err(err.into());
+            };
+
+            let mut datetimes = Datetimes::new();
+            let value = from_toml(value, ROOT_PATH, &mut datetimes)?;
+            return Ok((value, datetimes));
+        }
+    };
+
+    let mut datetimes = Datetimes::new();
+    let value = from_toml(toml::Value::Table(table), ROOT_PATH, &mut datetimes)?;
+    Ok((value, datetimes))
+}
+
+/// Whether TOML `text` has a comment anywhere, comments only live in the whitespace
+/// around tables, keys and values.
+fn toml_has_comments(text: &str) -> bool {
+    let Ok(doc) = text.parse::<toml_edit::DocumentMut>() else {
+        return text.contains('#');
+    };
+
+    commented(doc.trailing()) || table_has_comments(doc.as_table())
+}
+
+///
+fn table_has_comments(table: &toml_edit::Table) -> bool {
+    decor_has_comments(table.decor())
+        || table.iter().any(|(key, item)| {
+            table.key(key).is_some_and(key_has_comments) || item_has_comments(item)
+        })
+}
+
+///
+fn item_has_comments(item: &toml_edit::Item) -> bool {
+    match item {
+        &toml_edit::Item::None => false,
+        &toml_edit::Item::Value(ref value) => value_has_comments(value),
+        &toml_edit::Item::Table(ref table) => table_has_comments(table),
+        &toml_edit::Item::ArrayOfTables(ref tables) => tables.iter().any(table_has_comments),
+    }
+}
+
+///
+fn value_has_comments(value: &toml_edit::Value) -> bool {
+    decor_has_comments(value.decor())
+        || match value {
+            &toml_edit::Value::Array(ref values) => {
+                commented(values.trailing()) || values.iter().any(value_has_comments)
+            }
+            &toml_edit::Value::InlineTable(ref table) => {
+                commented(table.preamble())
+                    || table.iter().any(|(key, value)| {
+                        table.key(key).is_some_and(key_has_comments) || value_has_comments(value)
+                    })
+            }
+            &(toml_edit::Value::String(_)
+            | toml_edit::Value::Integer(_)
+            | toml_edit::Value::Float(_)
+            | toml_edit::Value::Boolean(_)
+            | toml_edit::Value::Datetime(_)) => false,
+        }
+}
+
+///
+fn key_has_comments(key: &toml_edit::Key) -> bool {
+    decor_has_comments(key.leaf_decor()) || decor_has_comments(key.dotted_decor())
+}
+
+///
+fn decor_has_comments(decor: &toml_edit::Decor) -> bool {
+    decor
+        .prefix()
+        .into_iter()
+        .chain(decor.suffix())
+        .any(commented)
+}
+
+/// Whether whitespace kept from the document has a comment in it.
+fn commented(raw: &toml_edit::RawString) -> bool {
+    raw.as_str().is_none_or(|raw| raw.contains('#'))
+}
+
+/// TOML datetimes become strings, their paths are added to `datetimes` so [`to_toml`]
+/// can turn them back into datetimes. `nan` and `inf` have nothing to become, so they are
+/// refused.
+fn from_toml(value: toml::Value, path: &str, datetimes: &mut Datetimes) -> anyhow::Result<Value> {
+    match value {
+        toml::Value::String(value) => Ok(Value::String(value)),
+        toml::Value::Integer(value) => Ok(Value::Number(value.into())),
+        toml::Value::Float(value) => serde_json::Number::from_f64(value)
+            .map(Value::Number)
+            .ok_or_else(|| anyhow!("{path} is {value}, which rover has no number for")),
+        toml::Value::Boolean(value) => Ok(Value::Bool(value)),
+        toml::Value::Datetime(value) => {
+            datetimes.insert(path.to_owned());
+            Ok(Value::String(value.to_string()))
+        }
+        toml::Value::Array(values) => values
+            .into_iter()
+            .enumerate()
+            .map(|(idx, value)| from_toml(value, &format!("{path}/{idx}"), datetimes))
+            .collect::<anyhow::Result<_>>()
+            .map(Value::Array),
+        toml::Value::Table(table) => table
+            .into_iter()
+            .map(|(key, value)| {
+                let child = format!("{path}/{}", escape(&key));
+                let value = from_toml(value, &child, datetimes)?;
+                Ok((key, value))
+            })
+            .collect::<anyhow::Result<_>>()
+            .map(Value::Object),
+    }
+}
+
+///
+fn serialize_toml(value: &Value, datetimes: &Datetimes) -> anyhow::Result<String> {
+    let value = to_toml(value, ROOT_PATH, datetimes)?;
+    if let toml::Value::Table(ref table) = value {
+        Ok(toml::to_string_pretty(table)?)
+    } else {
+        // Values picked out of a document are shown inline
+        Ok(value.to_string())
+    }
+}
+
+///
+fn to_toml(value: &Value, path: &str, datetimes: &Datetimes) -> anyhow::Result<toml::Value> {
+    match value {
+        &Value::Null => bail!("TOML has no null value, replace or remove {path} before saving"),
+        &Value::Bool(value) => Ok(toml::Value::Boolean(value)),
+        &Value::Bytes(ref value) => Ok(toml::Value::String(STANDARD.encode(value))),
+        &Value::String(ref value) => match value.parse::<toml::value::Datetime>() {
+            // Quoted strings that look like dates stay strings
+            Ok(datetime) if datetime.to_string() == *value && datetimes.contains(path) => {
+                Ok(toml::Value::Datetime(datetime))
+            }
+            Ok(_) | Err(_) => Ok(toml::Value::String(value.clone())),
+        },
+        &Value::Number(ref number) => {
+            if let Some(number) = number.as_i64() {
+                Ok(toml::Value::Integer(number))
+            } else if number.is_u64() {
+                bail!("{path} is too large for a TOML integer");
+            } else {
+                let number = number
+                    .as_f64()
+                    .ok_or_else(|| anyhow!("{path} is not a valid TOML number"))?;
+                Ok(toml::Value::Float(number))
+            }
+        }
+        &Value::Array(ref values) => values
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| to_toml(value, &format!("{path}/{idx}"), datetimes))
+            .collect::<anyhow::Result<_>>()
+            .map(toml::Value::Array),
+        &Value::Object(ref map) => map
+            .iter()
+            .map(|(key, value)| {
+                let child = format!("{path}/{}", escape(key));
+                Ok((key.clone(), to_toml(value, &child, datetimes)?))
+            })
+            .collect::<anyhow::Result<_>>()
+            .map(toml::Value::Table),
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(format) = Self::from_extension(s) else {
+            let supported: Vec<_> = Self::ALL.iter().map(|format| format.extension()).collect();
+            bail!(
+                "Unsupported format: {s}, expected one of {}",
+                supported.join(", ")
+            );
+        };
+
+        Ok(format)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_path() -> anyhow::Result<()> {
+        let cases = [
+            ("openapi.json", Some(Format::Json)),
+            ("dir.d/config.YML", Some(Format::Yaml)),
+            ("openapi.json.tmpl", Some(Format::Json)),
+            ("values.yaml.template", Some(Format::Yaml)),
+            ("Cargo.toml", Some(Format::Toml)),
+            ("export.jsonl", Some(Format::JsonLines)),
+            ("logs.ndjson", Some(Format::JsonLines)),
+            ("tsconfig.jsonc", Some(Format::Json5)),
+            ("payload.msgpack", Some(Format::MessagePack)),
+            ("payload.cbor", Some(Format::Cbor)),
+            ("spec.openapi", None),
+            ("Dockerfile", None),
+        ];
+
+        for (path, expected) in cases {
+            let format = Format::from_path(path);
+            if format != expected {
+                bail!("Expected {:?} for {:?}, got {:?}", expected, path, format);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sniff() -> anyhow::Result<()> {
+        let cases = [
+            (r#"{"a": [1, 2]}"#, Format::Json),
+            ("  [1, 2]\n", Format::Json),
+            ("a: 1\nb: [1, 2]\n", Format::Yaml),
+            ("[a, b]\n", Format::Yaml),
+            ("{a: 1}", Format::Json5),
+            ("[package]\nname = \"rover\"\n", Format::Toml),
+            ("a = 1\n", Format::Toml),
+            ("", Format::Yaml),
+            ("{\"a\": 1}\n{\"a\": 2}\n", Format::JsonLines),
+            ("// settings\n{\"a\": 1,}\n", Format::Json5),
+        ];
+
+        for (text, expected) in cases {
+            let format = Format::sniff(text.as_bytes());
+            if format != expected {
+                bail!("Expected {:?} for {:?}, got {:?}", expected, text, format);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_has_comments() -> anyhow::Result<()> {
+        let cases = [
+            (Format::Yaml, "a: 1 # trailing\n", true),
+            (Format::Yaml, "a: &x 1 # important\nb: *x\n", true),
+            (Format::Yaml, "a: 'x' # after a quote\n", true),
+            (
+                Format::Yaml,
+                "a: 'x # quoted'\nb: \"y # quoted\"\nc: a#b\nd: |\n  # text\n",
+                false,
+            ),
+            (Format::YamlStream, "a: 1\n---\nb: 2 # trailing\n", true),
+            (Format::Toml, "b = 1 # keep me\n", true),
+            (Format::Toml, "a = [\n  1, # one\n]\n", true),
+            (Format::Toml, "[t] # table\na = 1\n", true),
+            (Format::Toml, "a = \"x # quoted\"\n\"k#\" = 1\n", false),
+        ];
+
+        for (format, text, expected) in cases {
+            let found = format.has_comments(text);
+            if found != expected {
+                bail!("Expected {:?} for {:?}, got {:?}", expected, text, found);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_round_trip() -> anyhow::Result<()> {
+        let text = "name = \"rover\"\nreleased = 2023-05-27\n\n[dependencies]\nlog = \"0.4\"\n";
+
+        let (value, datetimes) = Format::Toml.decode_document(text.as_bytes())?;
+        let Value::Object(ref map) = value else {
+            bail!("Expected a table, got {:?}", value);
+        };
+        let released = map.get("released");
+        if released != Some(&Value::String("2023-05-27".to_owned())) {
+            bail!("Expected datetime as a string, got {:?}", released);
+        }
+
+        let written = Format::Toml.encode_document(&value, &datetimes)?;
+        if written != text.as_bytes() {
+            bail!("Expected {:?}, got {:?}", text, String::from_utf8(written));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_quoted_date_stays_string() -> anyhow::Result<()> {
+        let text = "version = \"2024-01-01\"\n\n[[releases]]\ndate = 2023-05-27\n";
+
+        let (value, datetimes) = Format::Toml.decode_document(text.as_bytes())?;
+        let expected = Datetimes::from(["#/releases/0/date".to_owned()]);
+        if datetimes != expected {
+            bail!("Expected {:?}, got {:?}", expected, datetimes);
+        }
+
+        let written = Format::Toml.encode_document(&value, &datetimes)?;
+        if written != text.as_bytes() {
+            bail!("Expected {:?}, got {:?}", text, String::from_utf8(written));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_datetime_keys_with_pointer_characters() -> anyhow::Result<()> {
+        let text = "\"a/b\" = 2023-05-27\n\"a~1b\" = \"2024-01-01\"\n";
+
+        let (value, datetimes) = Format::Toml.decode_document(text.as_bytes())?;
+        let expected = Datetimes::from(["#/a~1b".to_owned()]);
+        if datetimes != expected {
+            bail!("Expected {:?}, got {:?}", expected, datetimes);
+        }
+
+        let written = Format::Toml.encode_document(&value, &datetimes)?;
+        if written != text.as_bytes() {
+            bail!("Expected {:?}, got {:?}", text, String::from_utf8(written));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_non_finite_float_is_rejected() -> anyhow::Result<()> {
+        for text in [
+            "[limits]\nmax = inf\n",
+            "[limits]\nmax = -inf\n",
+            "[limits]\nmax = nan\n",
+        ] {
+            match Format::Toml.decode_document(text.as_bytes()) {
+                Ok(value) => bail!("Expected {:?} to be rejected, got {:?}", text, value),
+                Err(err) if err.to_string().contains("#/limits/max") => {}
+                Err(err) => bail!("Expected the error to name the key, got {err}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_null_is_rejected() -> anyhow::Result<()> {
+        let value = Format::Json.parse(r#"{"package": {"name": null}}"#)?;
+
+        match Format::Toml.serialize(&value) {
+            Ok(written) => bail!("Expected an error, got {:?}", written),
+            Err(err) if err.to_string().contains("#/package/name") => Ok(()),
+            Err(err) => bail!(
+                "Expected the error to name the path, got {:?}",
+                err.to_string()
+            ),
+        }
+    }
+
+    #[test]
+    fn test_toml_inline_values() -> anyhow::Result<()> {
+        let value = Format::Toml.parse("[1, 2]")?;
+        let written = Format::Toml.serialize(&value)?;
+        if written != "[1, 2]" {
+            bail!("Expected {:?}, got {:?}", "[1, 2]", written);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_lines_round_trip() -> anyhow::Result<()> {
+        let text = "{\"id\":1,\"tags\":[\"a\"]}\n\n{\"id\":2,\"tags\":[]}\n";
+
+        let value = Format::JsonLines.parse(text)?;
+        let Value::Array(ref records) = value else {
+            bail!("Expected a list of records, got {:?}", value);
+        };
+        if records.len() != 2 {
+            bail!("Expected 2 records, got {}", records.len());
+        }
+
+        let written = Format::JsonLines.serialize(&value)?;
+        let expected = "{\"id\":1,\"tags\":[\"a\"]}\n{\"id\":2,\"tags\":[]}\n";
+        if written != expected {
+            bail!("Expected {:?}, got {:?}", expected, written);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_lines_error_names_line() -> anyhow::Result<()> {
+        match Format::JsonLines.parse("{\"id\":1}\n{\"id\":\n") {
+            Ok(value) => bail!("Expected an error, got {:?}", value),
+            Err(err) if err.to_string().contains("line 2") => Ok(()),
+            Err(err) => bail!(
+                "Expected the error to name the line, got {:?}",
+                err.to_string()
+            ),
+        }
+    }
+
+    #[test]
+    fn test_yaml_stream() -> anyhow::Result<()> {
+        let text = "a: 1\n---\nb: 2\n";
+
+        let format = Format::detect("manifests.yaml", text.as_bytes());
+        if format != Format::YamlStream {
+            bail!("Expected {:?}, got {:?}", Format::YamlStream, format);
+        }
+
+        let value = format.parse(text)?;
+        let written = format.serialize(&value)?;
+        if written != text {
+            bail!("Expected {:?}, got {:?}", text, written);
+        }
+
+        let single = Format::detect("single.yaml", b"---\na: 1\n");
+        if single != Format::Yaml {
+            bail!("Expected {:?}, got {:?}", Format::Yaml, single);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_with_comments() -> anyhow::Result<()> {
+        let text = "{\n  // the answer\n  \"a\": 42, /* \"b\": 1 */\n  \"url\": \"http://x\",\n}\n";
+
+        let format = Format::detect("tsconfig.json", text.as_bytes());
+        if format != Format::Json5 {
+            bail!("Expected {:?}, got {:?}", Format::Json5, format);
+        }
+
+        let value = format.parse(text)?;
+        let expected = Format::Json.parse(r#"{"a": 42, "url": "http://x"}"#)?;
+        if value != expected {
+            bail!("Expected {:?}, got {:?}", expected, value);
+        }
+
+        let blanked = blank_comments(text);
+        let expected =
+            "{\n               \n  \"a\": 42,             \n  \"url\": \"http://x\",\n}\n";
+        if blanked != expected {
+            bail!("Expected {:?}, got {:?}", expected, blanked);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_round_trip() -> anyhow::Result<()> {
+        let mut value = Format::Json.parse(r#"{"id": 7, "name": "rover", "ratio": 0.5}"#)?;
+        if let Value::Object(ref mut map) = value {
+            map.insert("payload".to_owned(), Value::Bytes(vec![0, 159, 255]));
+        }
+
+        for format in [Format::MessagePack, Format::Cbor] {
+            let data = format.encode(&value)?;
+
+            let sniffed = Format::sniff(&data);
+            if sniffed != format {
+                bail!("Expected {:?}, got {:?}", format, sniffed);
+            }
+
+            let decoded = format.decode(&data)?;
+            if decoded != value {
+                bail!("Expected {:?}, got {:?}", value, decoded);
+            }
+        }
+
+        let text = Format::Json.serialize(&value)?;
+        if !text.contains(r#""payload": "AJ//""#) {
+            bail!("Expected bytes as base64, got {:?}", text);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,698 @@
+//! Key bindings of every page, the defaults overridden by the `[keys]` tables of the
+//! [config file](crate::config).
+//!
+//! ```toml
+//! [keys.nav]
+//! "<C-d>" = "down"
+//! "<C-u>" = "up"
+//! "gg" = "top"
+//! "g" = "none"
+//! ```
+
+use core::str::FromStr;
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context as _};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+use crate::state::Page;
+
+/// A key together with the modifiers held down while pressing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    ///
+    code: KeyCode,
+
+    ///
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    ///
+    pub const fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self { code, modifiers }
+    }
+
+    /// The character typed with this key, if it is plain text.
+    pub fn text(self) -> Option<char> {
+        if let KeyCode::Char(ch) = self.code {
+            self.modifiers.is_empty().then_some(ch)
+        } else {
+            None
+        }
+    }
+
+    /// Keys of `notation`, written like `gg`, `<C-e>` or `<Esc>` in vim.
+    pub fn parse_sequence(notation: &str) -> anyhow::Result<Vec<Self>> {
+        let mut keys = Vec::new();
+        let mut rest = notation;
+        while !rest.is_empty() {
+            let named = rest
+                .strip_prefix('<')
+                .and_then(|inner| inner.split_once('>'))
+                .filter(|&(name, _)| !name.is_empty());
+            if let Some((name, after)) = named {
+                keys.push(name.parse()?);
+                rest = after;
+                continue;
+            }
+
+            let mut chars = rest.chars();
+            if let Some(ch) = chars.next() {
+                keys.push(Self::new(KeyCode::Char(ch), KeyModifiers::NONE));
+            }
+            rest = chars.as_str();
+        }
+
+        if keys.is_empty() {
+            bail!("Empty key sequence");
+        }
+
+        Ok(keys)
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        // Shifted characters already arrive in upper case
+        let modifiers = if let KeyCode::Char(_) = event.code {
+            event.modifiers.difference(KeyModifiers::SHIFT)
+        } else {
+            event.modifiers
+        };
+
+        Self::new(event.code, modifiers)
+    }
+}
+
+/// Parses the inside of `<...>`, like `C-e`, `S-Up` or `Enter`.
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut name = s;
+        while let Some((modifier, rest)) =
+            name.split_once('-').filter(|&(_, rest)| !rest.is_empty())
+        {
+            modifiers |= match modifier.to_ascii_uppercase().as_str() {
+                "C" => KeyModifiers::CONTROL,
+                "A" | "M" => KeyModifiers::ALT,
+                "S" => KeyModifiers::SHIFT,
+                _ => bail!("Unknown modifier {modifier} in <{s}>"),
+            };
+            name = rest;
+        }
+
+        let code = match name.to_ascii_lowercase().as_str() {
+            "enter" | "cr" | "return" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "bs" | "backspace" => KeyCode::Backspace,
+            "tab" => KeyCode::Tab,
+            "space" => KeyCode::Char(' '),
+            "lt" => KeyCode::Char('<'),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "del" | "delete" => KeyCode::Delete,
+            lower => {
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    // Terminals report <C-E> as <C-e>
+                    (Some(ch), None) if modifiers.contains(KeyModifiers::CONTROL) => {
+                        KeyCode::Char(ch.to_ascii_lowercase())
+                    }
+                    (Some(ch), None) => KeyCode::Char(ch),
+                    _ => match lower.strip_prefix('f').map(str::parse) {
+                        Some(Ok(number)) => KeyCode::F(number),
+                        Some(Err(_)) | None => bail!("Unknown key <{s}>"),
+                    },
+                }
+            }
+        };
+
+        if let KeyCode::Char(_) = code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+/// What a key sequence does, named in the config file in snake case.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Move to the previously selected path.
+    Back,
+    /// Highlight the next item.
+    Down,
+    /// Highlight the previous item.
+    Up,
+    /// Swap the highlighted item with the next one.
+    MoveDown,
+    /// Swap the highlighted item with the previous one.
+    MoveUp,
+    /// Select the highlighted item, or pick it in pick mode.
+    Confirm,
+    /// Select the highlighted item.
+    Select,
+    ///
+    Top,
+    ///
+    Bottom,
+    ///
+    Undo,
+    ///
+    Redo,
+    /// Edit the highlighted value in `$EDITOR`.
+    Edit,
+    ///
+    Save,
+    ///
+    SaveAs,
+    ///
+    Import,
+    ///
+    Export,
+    ///
+    Search,
+    /// Quit, unless there are unsaved changes.
+    Quit,
+    /// Quit without saving.
+    ForceQuit,
+    /// Clear the status, or leave the page when it is clear already.
+    Cancel,
+    /// Accept the input of a prompt or search.
+    Submit,
+    /// Remove the last character of the input.
+    DeleteChar,
+    /// Open the `:` command line.
+    OpenCommandLine,
+    /// Complete the command or pointer in the command line.
+    Complete,
+    /// Mark the highlighted item with the letter typed next.
+    SetMark,
+    /// Go to the item marked with the letter typed next.
+    JumpToMark,
+    /// Open the list of marks.
+    Marks,
+    /// Delete the mark selected in the list of marks.
+    DeleteMark,
+    /// Go back to where the last jump started.
+    JumpBack,
+    /// Redo a jump gone back from.
+    JumpForward,
+    /// List the `$ref`s pointing at the highlighted item.
+    FindUsages,
+    /// List the broken `$ref`s of the document.
+    Problems,
+}
+
+impl Command {
+    ///
+    const ALL: [Self; 32] = [
+        Self::Back,
+        Self::Down,
+        Self::Up,
+        Self::MoveDown,
+        Self::MoveUp,
+        Self::Confirm,
+        Self::Select,
+        Self::Top,
+        Self::Bottom,
+        Self::Undo,
+        Self::Redo,
+        Self::Edit,
+        Self::Save,
+        Self::SaveAs,
+        Self::Import,
+        Self::Export,
+        Self::Search,
+        Self::Quit,
+        Self::ForceQuit,
+        Self::Cancel,
+        Self::Submit,
+        Self::DeleteChar,
+        Self::OpenCommandLine,
+        Self::Complete,
+        Self::SetMark,
+        Self::JumpToMark,
+        Self::Marks,
+        Self::DeleteMark,
+        Self::JumpBack,
+        Self::JumpForward,
+        Self::FindUsages,
+        Self::Problems,
+    ];
+
+    ///
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Back => "back",
+            Self::Down => "down",
+            Self::Up => "up",
+            Self::MoveDown => "move_down",
+            Self::MoveUp => "move_up",
+            Self::Confirm => "confirm",
+            Self::Select => "select",
+            Self::Top => "top",
+            Self::Bottom => "bottom",
+            Self::Undo => "undo",
+            Self::Redo => "redo",
+            Self::Edit => "edit",
+            Self::Save => "save",
+            Self::SaveAs => "save_as",
+            Self::Import => "import",
+            Self::Export => "export",
+            Self::Search => "search",
+            Self::Quit => "quit",
+            Self::ForceQuit => "force_quit",
+            Self::Cancel => "cancel",
+            Self::Submit => "submit",
+            Self::DeleteChar => "delete_char",
+            Self::OpenCommandLine => "command_line",
+            Self::Complete => "complete",
+            Self::SetMark => "set_mark",
+            Self::JumpToMark => "jump_to_mark",
+            Self::Marks => "marks",
+            Self::DeleteMark => "delete_mark",
+            Self::JumpBack => "jump_back",
+            Self::JumpForward => "jump_forward",
+            Self::FindUsages => "find_usages",
+            Self::Problems => "problems",
+        }
+    }
+
+    /// Whether the command does anything on `page`.
+    const fn available_on(self, page: Page) -> bool {
+        match self {
+            Self::Cancel => true,
+            Self::Down | Self::Up => !matches!(
+                page,
+                Page::ImportPrompt | Page::ExportPrompt | Page::SaveAsPrompt
+            ),
+            Self::Complete => matches!(page, Page::CommandLine),
+            Self::DeleteMark => matches!(page, Page::Marks),
+            Self::Submit => !matches!(page, Page::Nav),
+            Self::DeleteChar => !matches!(page, Page::Nav | Page::Marks),
+            Self::Back
+            | Self::MoveDown
+            | Self::MoveUp
+            | Self::Confirm
+            | Self::Select
+            | Self::Top
+            | Self::Bottom
+            | Self::Undo
+            | Self::Redo
+            | Self::Edit
+            | Self::Save
+            | Self::SaveAs
+            | Self::Import
+            | Self::Export
+            | Self::Search
+            | Self::Quit
+            | Self::ForceQuit
+            | Self::OpenCommandLine
+            | Self::SetMark
+            | Self::JumpToMark
+            | Self::Marks
+            | Self::JumpBack
+            | Self::JumpForward
+            | Self::FindUsages
+            | Self::Problems => matches!(page, Page::Nav),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|command| command.name() == s)
+            .ok_or_else(|| {
+                let supported: Vec<_> = Self::ALL.iter().map(|command| command.name()).collect();
+                anyhow!(
+                    "Unknown command {s}, expected none or one of {}",
+                    supported.join(", ")
+                )
+            })
+    }
+}
+
+/// Name of `page` in the `[keys]` tables.
+const fn page_name(page: Page) -> &'static str {
+    match page {
+        Page::Nav => "nav",
+        Page::Search => "search",
+        Page::ImportPrompt => "import",
+        Page::ExportPrompt => "export",
+        Page::SaveAsPrompt => "save_as",
+        Page::CommandLine => "command",
+        Page::Marks => "marks",
+        Page::Usages => "usages",
+        Page::Problems => "problems",
+    }
+}
+
+///
+const PAGES: [Page; 9] = [
+    Page::Nav,
+    Page::Search,
+    Page::ImportPrompt,
+    Page::ExportPrompt,
+    Page::SaveAsPrompt,
+    Page::CommandLine,
+    Page::Marks,
+    Page::Usages,
+    Page::Problems,
+];
+
+/// Bindings of the navigation view.
+const NAV: [(&str, Command); 37] = [
+    ("h", Command::Back),
+    ("<BS>", Command::Back),
+    ("<Left>", Command::Back),
+    ("j", Command::Down),
+    ("<Down>", Command::Down),
+    ("<C-n>", Command::Down),
+    ("J", Command::MoveDown),
+    ("k", Command::Up),
+    ("<Up>", Command::Up),
+    ("<C-p>", Command::Up),
+    ("K", Command::MoveUp),
+    ("<Enter>", Command::Confirm),
+    ("l", Command::Select),
+    ("<Right>", Command::Select),
+    ("I", Command::Import),
+    ("O", Command::Export),
+    ("u", Command::Undo),
+    ("r", Command::Redo),
+    ("<C-e>", Command::Edit),
+    ("<C-s>", Command::Save),
+    ("S", Command::SaveAs),
+    ("g", Command::Top),
+    ("G", Command::Bottom),
+    ("/", Command::Search),
+    ("<C-q>", Command::ForceQuit),
+    ("q", Command::Quit),
+    ("<Esc>", Command::Quit),
+    ("<C-c>", Command::Cancel),
+    (":", Command::OpenCommandLine),
+    ("m", Command::SetMark),
+    ("'", Command::JumpToMark),
+    ("M", Command::Marks),
+    ("<C-o>", Command::JumpBack),
+    // Terminals send <C-i> as <Tab>
+    ("<C-i>", Command::JumpForward),
+    ("<Tab>", Command::JumpForward),
+    ("U", Command::FindUsages),
+    ("P", Command::Problems),
+];
+
+/// Bindings of the search view.
+const SEARCH: [(&str, Command); 8] = [
+    ("<Down>", Command::Down),
+    ("<C-n>", Command::Down),
+    ("<Up>", Command::Up),
+    ("<C-p>", Command::Up),
+    ("<Enter>", Command::Submit),
+    ("<BS>", Command::DeleteChar),
+    ("<Esc>", Command::Cancel),
+    ("<C-c>", Command::Cancel),
+];
+
+/// Bindings of the import, export and save as prompts.
+const PROMPT: [(&str, Command); 4] = [
+    ("<Enter>", Command::Submit),
+    ("<BS>", Command::DeleteChar),
+    ("<Esc>", Command::Cancel),
+    ("<C-c>", Command::Cancel),
+];
+
+/// Bindings of the command line.
+const COMMAND_LINE: [(&str, Command); 9] = [
+    ("<Enter>", Command::Submit),
+    ("<BS>", Command::DeleteChar),
+    ("<Tab>", Command::Complete),
+    ("<Up>", Command::Up),
+    ("<C-p>", Command::Up),
+    ("<Down>", Command::Down),
+    ("<C-n>", Command::Down),
+    ("<Esc>", Command::Cancel),
+    ("<C-c>", Command::Cancel),
+];
+
+/// Bindings of the list of marks.
+const MARKS: [(&str, Command); 11] = [
+    ("j", Command::Down),
+    ("<Down>", Command::Down),
+    ("<C-n>", Command::Down),
+    ("k", Command::Up),
+    ("<Up>", Command::Up),
+    ("<C-p>", Command::Up),
+    ("<Enter>", Command::Submit),
+    ("d", Command::DeleteMark),
+    ("q", Command::Cancel),
+    ("<Esc>", Command::Cancel),
+    ("<C-c>", Command::Cancel),
+];
+
+/// Bindings of the lists of usages and problems.
+const LIST: [(&str, Command); 10] = [
+    ("j", Command::Down),
+    ("<Down>", Command::Down),
+    ("<C-n>", Command::Down),
+    ("k", Command::Up),
+    ("<Up>", Command::Up),
+    ("<C-p>", Command::Up),
+    ("<Enter>", Command::Submit),
+    ("q", Command::Cancel),
+    ("<Esc>", Command::Cancel),
+    ("<C-c>", Command::Cancel),
+];
+
+/// What the keys pressed so far amount to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lookup {
+    ///
+    Command(Command),
+
+    /// The keys start a longer sequence, wait for the next one.
+    Pending,
+
+    ///
+    Unbound,
+}
+
+///
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "HashMap<String, HashMap<String, String>>")]
+pub struct Keymap {
+    ///
+    bindings: HashMap<Page, HashMap<Vec<Key>, Command>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut bindings = HashMap::new();
+        for page in PAGES {
+            let defaults = match page {
+                Page::Nav => NAV.as_slice(),
+                Page::Search => SEARCH.as_slice(),
+                Page::ImportPrompt | Page::ExportPrompt | Page::SaveAsPrompt => PROMPT.as_slice(),
+                Page::CommandLine => COMMAND_LINE.as_slice(),
+                Page::Marks => MARKS.as_slice(),
+                Page::Usages | Page::Problems => LIST.as_slice(),
+            };
+
+            let page_bindings = defaults
+                .iter()
+                .filter_map(|&(notation, command)| {
+                    Some((Key::parse_sequence(notation).ok()?, command))
+                })
+                .collect();
+            bindings.insert(page, page_bindings);
+        }
+
+        Self { bindings }
+    }
+}
+
+impl TryFrom<HashMap<String, HashMap<String, String>>> for Keymap {
+    type Error = anyhow::Error;
+
+    fn try_from(tables: HashMap<String, HashMap<String, String>>) -> Result<Self, Self::Error> {
+        let mut keymap = Self::default();
+        for (name, table) in tables {
+            let Some(page) = PAGES.into_iter().find(|&page| page_name(page) == name) else {
+                let supported: Vec<_> = PAGES.into_iter().map(page_name).collect();
+                bail!(
+                    "Unknown page keys.{name}, expected one of {}",
+                    supported.join(", ")
+                );
+            };
+
+            for (notation, command) in table {
+                keymap
+                    .bind(page, &notation, &command)
+                    .with_context(|| format!("Invalid binding keys.{name}.{notation:?}"))?;
+            }
+        }
+
+        Ok(keymap)
+    }
+}
+
+impl Keymap {
+    /// Binds `notation` to the command named `command` on `page`, `none` removes the binding.
+    fn bind(&mut self, page: Page, notation: &str, command: &str) -> anyhow::Result<()> {
+        let keys = Key::parse_sequence(notation)?;
+        let bindings = self.bindings.entry(page).or_default();
+        if command == "none" {
+            bindings.remove(&keys);
+            return Ok(());
+        }
+
+        let command = command.parse::<Command>()?;
+        if !command.available_on(page) {
+            bail!("{} does nothing on {}", command.name(), page_name(page));
+        }
+        bindings.insert(keys, command);
+
+        Ok(())
+    }
+
+    /// What `keys`, pressed one after the other on `page`, are bound to.
+    pub fn lookup(&self, page: Page, keys: &[Key]) -> Lookup {
+        let Some(bindings) = self.bindings.get(&page) else {
+            return Lookup::Unbound;
+        };
+
+        if let Some(&command) = bindings.get(keys) {
+            return Lookup::Command(command);
+        }
+
+        if bindings
+            .keys()
+            .any(|sequence| sequence.len() > keys.len() && sequence.starts_with(keys))
+        {
+            Lookup::Pending
+        } else {
+            Lookup::Unbound
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::bail;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_sequence() -> anyhow::Result<()> {
+        let cases = [
+            (
+                "gg",
+                vec![Key::new(KeyCode::Char('g'), KeyModifiers::NONE); 2],
+            ),
+            (
+                "<C-E>",
+                vec![Key::new(KeyCode::Char('e'), KeyModifiers::CONTROL)],
+            ),
+            ("<S-Up>", vec![Key::new(KeyCode::Up, KeyModifiers::SHIFT)]),
+            (
+                "<lt><Esc>",
+                vec![
+                    Key::new(KeyCode::Char('<'), KeyModifiers::NONE),
+                    Key::new(KeyCode::Esc, KeyModifiers::NONE),
+                ],
+            ),
+            ("<", vec![Key::new(KeyCode::Char('<'), KeyModifiers::NONE)]),
+        ];
+
+        for (notation, expected) in cases {
+            let keys = Key::parse_sequence(notation)?;
+            if keys != expected {
+                bail!("Expected {:?} for {:?}, got {:?}", expected, notation, keys);
+            }
+        }
+
+        for notation in ["", "<Nope>", "<X-a>"] {
+            if let Ok(keys) = Key::parse_sequence(notation) {
+                bail!("Expected {:?} to be refused, got {:?}", notation, keys);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup() -> anyhow::Result<()> {
+        let tables = HashMap::from([(
+            "nav".to_owned(),
+            HashMap::from([
+                ("<C-d>".to_owned(), "down".to_owned()),
+                ("gg".to_owned(), "top".to_owned()),
+                ("g".to_owned(), "none".to_owned()),
+            ]),
+        )]);
+        let keymap = Keymap::try_from(tables)?;
+
+        let g = Key::new(KeyCode::Char('g'), KeyModifiers::NONE);
+        let cases = [
+            (Page::Nav, vec![g], Lookup::Pending),
+            (Page::Nav, vec![g, g], Lookup::Command(Command::Top)),
+            (
+                Page::Nav,
+                vec![Key::new(KeyCode::Char('d'), KeyModifiers::CONTROL)],
+                Lookup::Command(Command::Down),
+            ),
+            (
+                Page::Nav,
+                vec![Key::new(KeyCode::Char('j'), KeyModifiers::NONE)],
+                Lookup::Command(Command::Down),
+            ),
+            (Page::Search, vec![g], Lookup::Unbound),
+        ];
+
+        for (page, keys, expected) in cases {
+            let lookup = keymap.lookup(page, &keys);
+            if lookup != expected {
+                bail!("Expected {:?} for {:?}, got {:?}", expected, keys, lookup);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_bindings() -> anyhow::Result<()> {
+        let cases = [
+            ("nav", "x", "fly"),
+            ("nav", "x", "submit"),
+            ("search", "x", "save"),
+            ("popup", "x", "down"),
+        ];
+
+        for (page, notation, command) in cases {
+            let tables = HashMap::from([(
+                page.to_owned(),
+                HashMap::from([(notation.to_owned(), command.to_owned())]),
+            )]);
+            if Keymap::try_from(tables).is_ok() {
+                bail!("Expected {:?} on {:?} to be refused", command, page);
+            }
+        }
+
+        Ok(())
+    }
+}
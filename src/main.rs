@@ -24,35 +24,68 @@
 
 extern crate alloc;
 use alloc::sync::Arc;
-use log4rs::{append::file::FileAppender, config::Appender, Config};
+use log4rs::{append::file::FileAppender, config::Appender};
 
-use std::{env, sync::Mutex};
+use std::{
+    env,
+    io::{self, IsTerminal as _, Write as _},
+    process::ExitCode,
+    sync::Mutex,
+};
 
-use anyhow::{anyhow, Context};
+use action::Action;
+use anyhow::{bail, Context};
+use clap::{Arg, ArgAction, Command};
+use commands::write_value;
+use config::Config;
 use events::event_listener;
+use format::Format;
 use lifecycle::Application;
+use pointer::ValuePointer;
 use redux_rs::Store;
-use state::State;
+use state::{Page, Pick, State, StatusMessage};
 use ui::configure_terminal;
-use util::fetch_document;
+use util::{drops_comments, fetch_document, is_writable, render_doc, split_fragment, STDIN};
+use value::Value;
 
 mod action;
+mod commands;
+mod compression;
+mod config;
+mod diagnostics;
 mod events;
+mod ex;
+mod format;
+mod keymap;
 mod lifecycle;
+mod marks;
 mod pointer;
 mod reducer;
+mod reference;
 mod search;
+mod source;
 mod state;
 mod ui;
 mod util;
 mod value;
 
+/// Exit code when pick mode is left without choosing anything.
+const NOT_PICKED: u8 = 1;
+
 ///
-fn setup_logging() -> anyhow::Result<()> {
+fn setup_logging(config: &Config) -> anyhow::Result<()> {
     dotenv::dotenv().ok();
 
     let rover_home = env::var("ROVER_HOME")
-        .unwrap_or_else(|_| env::var("HOME").unwrap_or_else(|_| String::from(".")));
+        .ok()
+        .or_else(|| {
+            config
+                .log
+                .dir
+                .as_ref()
+                .map(|dir| dir.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| env::var("HOME").unwrap_or_else(|_| String::from(".")));
 
     let log_file = FileAppender::builder()
         .encoder(Box::new(log4rs::encode::pattern::PatternEncoder::new(
@@ -61,31 +94,181 @@ fn setup_logging() -> anyhow::Result<()> {
         .build(format!("{rover_home}/rover.log"))
         .context("Failed to create log file.")?;
 
-    let config = Config::builder()
+    let log_config = log4rs::Config::builder()
         .appender(Appender::builder().build("logfile", Box::new(log_file)))
         .build(
             log4rs::config::Root::builder()
                 .appender("logfile")
-                .build(log::LevelFilter::Info),
+                .build(config.log_level()?),
         )
         .context("Failed to create log configuration.")?;
 
-    log4rs::init_config(config).context("Failed to initialize log configuration.")?;
+    log4rs::init_config(log_config).context("Failed to initialize log configuration.")?;
 
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args: Vec<_> = env::args().collect();
-    let file_name = args
-        .get(1)
-        .ok_or_else(|| anyhow!("Missing filename in argument list."))?;
+///
+fn command() -> Command {
+    Command::new("rover")
+        .about("Explore and edit JSON, YAML, TOML, MessagePack and CBOR documents in the terminal")
+        .args_conflicts_with_subcommands(true)
+        .arg(Arg::new("file").help(
+            "Document to open, `-` or nothing to read standard input. A pointer can be appended \
+             like `spec.yaml#/info` to start there",
+        ))
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .short('f')
+                .global(true)
+                .help("Format of the document, detected from the file name or contents by default")
+                .value_parser(|format: &str| format.parse::<Format>()),
+        )
+        .arg(
+            Arg::new("at")
+                .long("at")
+                .help("Start at the value this JSON pointer points to"),
+        )
+        .arg(
+            Arg::new("search")
+                .long("search")
+                .help("Start in the search view with this query"),
+        )
+        .arg(
+            Arg::new("pick")
+                .long("pick")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("pointer")
+                .value_parser(["pointer", "value"])
+                .help("Print the pointer, or the value, chosen with <Enter> instead of editing"),
+        )
+        .arg(
+            Arg::new("read-only")
+                .long("read-only")
+                .action(ArgAction::SetTrue)
+                .help("Refuse every change to the document, the default for files that can't be written"),
+        )
+        .subcommands(commands::subcommands())
+}
+
+/// Actions applied before the first render, for the `--at` and `--search` options.
+fn startup_actions(doc: &Value, at: Option<&str>, search: Option<&str>) -> Vec<Action> {
+    let mut actions = Vec::new();
+    if let Some(path) = at {
+        if path
+            .parse::<ValuePointer>()
+            .and_then(|pointer| pointer.get(doc).map(|_| ()))
+            .is_err()
+        {
+            actions.push(Action::SetStatus {
+                message: StatusMessage::Warn(format!(
+                    "No value at {path}, starting at the closest one"
+                )),
+                timeout: None,
+            });
+        }
+        actions.push(Action::NavGoto {
+            path: path.to_owned(),
+        });
+    }
+    if let Some(query) = search {
+        actions.push(Action::SearchSetAllPaths);
+        actions.push(Action::SearchSetValue {
+            value: query.to_owned(),
+        });
+        actions.push(Action::SetCurrentPage { page: Page::Search });
+    }
+
+    actions
+}
+
+/// Writes what was chosen in pick mode to standard output, returns whether anything was.
+fn write_picked(state: &State, pick: Pick) -> anyhow::Result<bool> {
+    let Some(ref path) = state.picked else {
+        return Ok(false);
+    };
 
-    let doc = fetch_document(file_name)?;
-    let initial_state = State::new(doc, file_name.clone());
+    match pick {
+        Pick::Pointer => writeln!(io::stdout(), "{path}")?,
+        Pick::Value => {
+            let value = path.parse::<ValuePointer>()?.get(&state.doc)?;
+            write_value(value, state.format().fragment())?;
+        }
+    }
 
-    setup_logging()?;
+    Ok(true)
+}
+
+/// The edited document handed on to the next command, without the comments that can't be kept.
+fn piped_output(state: &State) -> anyhow::Result<Vec<u8>> {
+    let persisted = state.persisted.as_ref();
+    let drops =
+        persisted.is_some_and(|persisted| drops_comments(&state.doc, persisted, state.format()));
+    if drops {
+        writeln!(
+            io::stderr(),
+            "rover: The comments of the document can't be kept"
+        )?;
+    }
+
+    let source = persisted
+        .and_then(|persisted| persisted.source.as_ref())
+        .filter(|_| !drops);
+    let datetimes = persisted
+        .map(|persisted| persisted.datetimes.clone())
+        .unwrap_or_default();
+    render_doc(&state.doc, source, state.format(), &datetimes)
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Unable to start the runtime")?
+        .block_on(run())
+}
+
+///
+async fn run() -> anyhow::Result<ExitCode> {
+    let matches = command().get_matches();
+    if let Some((name, matches)) = matches.subcommand() {
+        return Ok(commands::run(name, matches));
+    }
+
+    let (file_name, fragment) = match matches.get_one::<String>("file") {
+        Some(argument) => split_fragment(argument),
+        None if !io::stdin().is_terminal() => (STDIN, None),
+        None => bail!("Missing filename in argument list."),
+    };
+    let file_name = &file_name.to_owned();
+    let at = matches
+        .get_one::<String>("at")
+        .map(String::as_str)
+        .or(fragment);
+    let format = matches.get_one::<Format>("format").copied();
+    let pick = matches
+        .get_one::<String>("pick")
+        .map(|pick| pick.parse())
+        .transpose()?;
+
+    let config = Config::load()?;
+    let (doc, persisted) = fetch_document(file_name, format)?;
+
+    setup_logging(&config)?;
+
+    let mut initial_state = State::new(doc, file_name.clone(), persisted, config);
+    initial_state.pick = pick;
+    initial_state.read_only = matches.get_flag("read-only") || !is_writable(file_name);
+    initial_state.marks = marks::load(file_name);
+    let initial_state = startup_actions(
+        &initial_state.doc,
+        at,
+        matches.get_one::<String>("search").map(String::as_str),
+    )
+    .into_iter()
+    .fold(initial_state, reducer::reducer);
 
     //
     //  !!!PANICS beyond this point will ruin the terminal state!!!
@@ -108,18 +291,46 @@ async fn main() -> anyhow::Result<()> {
         })
         .await;
 
-    let result = tokio::spawn(event_listener(store, Arc::clone(&lifecycle))).await?;
+    let event_lifecycle = Arc::clone(&lifecycle);
+    let (store, result) = tokio::spawn(async move {
+        let result = event_listener(&store, event_lifecycle).await;
+        (store, result)
+    })
+    .await?;
 
     // At this point we just really want to fix the terminal if we can
-    let mut lifecycle = match lifecycle.lock() {
-        Ok(lock) => lock,
-        Err(err) => err.into_inner(),
+    {
+        let mut lifecycle = match lifecycle.lock() {
+            Ok(lock) => lock,
+            Err(err) => err.into_inner(),
+        };
+        lifecycle.suspend()?;
     };
-    lifecycle.suspend()?;
 
     if let Err(err) = result {
         log::error!("Error in event listener: {err}");
     }
 
-    Ok(())
+    if let Some(pick) = pick {
+        let picked = store
+            .select(move |state: &State| write_picked(state, pick))
+            .await?;
+
+        // Like fzf, leaving without choosing anything is a failure
+        return Ok(if picked {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::from(NOT_PICKED)
+        });
+    }
+
+    // When piped through, hand the edited document on to the next command. A file is
+    // saved in place instead
+    if file_name == STDIN && !io::stdout().is_terminal() {
+        let output = store.select(piped_output).await?;
+
+        io::stdout().write_all(&output)?;
+    }
+
+    Ok(ExitCode::SUCCESS)
 }
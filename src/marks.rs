@@ -0,0 +1,138 @@
+//! Marks set with `m<letter>`, kept per document in a state file so they survive restarts.
+//!
+//! The file, `$XDG_STATE_HOME/rover/marks.json` or `~/.local/state/rover/marks.json`, maps
+//! the absolute path of every document to its marks.
+
+extern crate alloc;
+use alloc::collections::BTreeMap;
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+
+use crate::util::STDIN;
+
+/// Pointers by the letter they are marked with.
+pub type Marks = BTreeMap<char, String>;
+
+/// Marks of every document, by absolute path.
+type MarkFile = BTreeMap<String, Marks>;
+
+/// Whether `letter` can name a mark.
+pub const fn is_mark(letter: char) -> bool {
+    letter.is_ascii_alphabetic()
+}
+
+/// Location of the state file, `None` when there is no home to put it in.
+fn state_file() -> Option<PathBuf> {
+    let state_home = env::var("XDG_STATE_HOME")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var("HOME")
+                .ok()
+                .map(|home| Path::new(&home).join(".local").join("state"))
+        })?;
+
+    Some(state_home.join("rover").join("marks.json"))
+}
+
+/// Key of `file_name` in the state file, documents read from standard input have none.
+fn document_key(file_name: &str) -> Option<String> {
+    if file_name == STDIN {
+        return None;
+    }
+
+    let path = fs::canonicalize(file_name).ok()?;
+    Some(path.to_string_lossy().into_owned())
+}
+
+///
+fn read(state_file: &Path) -> anyhow::Result<MarkFile> {
+    match fs::read(state_file) {
+        Ok(contents) => serde_json::from_slice(&contents)
+            .with_context(|| format!("Unable to parse {}", state_file.display())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(MarkFile::new()),
+        Err(err) => Err(err).with_context(|| format!("Unable to read {}", state_file.display())),
+    }
+}
+
+/// Marks saved for `file_name`.
+pub fn load(file_name: &str) -> Marks {
+    state_file()
+        .zip(document_key(file_name))
+        .and_then(|(state_file, key)| load_from(&state_file, &key).ok())
+        .unwrap_or_default()
+}
+
+///
+fn load_from(state_file: &Path, key: &str) -> anyhow::Result<Marks> {
+    Ok(read(state_file)?.remove(key).unwrap_or_default())
+}
+
+/// Replaces the marks saved for `file_name`.
+pub fn save(file_name: &str, marks: &Marks) -> anyhow::Result<()> {
+    let (Some(state_file), Some(key)) = (state_file(), document_key(file_name)) else {
+        return Ok(());
+    };
+
+    save_to(&state_file, &key, marks)
+}
+
+///
+fn save_to(state_file: &Path, key: &str, marks: &Marks) -> anyhow::Result<()> {
+    let mut all = read(state_file)?;
+    if marks.is_empty() {
+        all.remove(key);
+    } else {
+        all.insert(key.to_owned(), marks.clone());
+    }
+
+    if let Some(dir) = state_file.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Unable to create {}", dir.display()))?;
+    }
+    fs::write(state_file, serde_json::to_vec_pretty(&all)?)
+        .with_context(|| format!("Unable to write {}", state_file.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::bail;
+
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() -> anyhow::Result<()> {
+        let dir = env::temp_dir().join(format!("rover-marks-{}", std::process::id()));
+        let state_file = dir.join("rover").join("marks.json");
+
+        let pets = Marks::from([('p', "#/components/schemas/Pet".to_owned())]);
+        let users = Marks::from([('u', "#/components/schemas/User".to_owned())]);
+        save_to(&state_file, "/specs/pets.yaml", &pets)?;
+        save_to(&state_file, "/specs/users.yaml", &users)?;
+
+        let loaded = load_from(&state_file, "/specs/pets.yaml")?;
+        if loaded != pets {
+            bail!("Expected {:?}, got {:?}", pets, loaded);
+        }
+
+        save_to(&state_file, "/specs/users.yaml", &Marks::new())?;
+        let loaded = load_from(&state_file, "/specs/users.yaml")?;
+        if !loaded.is_empty() {
+            bail!("Expected no marks, got {:?}", loaded);
+        }
+
+        let loaded = load_from(&state_file, "/specs/pets.yaml")?;
+        if loaded != pets {
+            bail!("Expected {:?}, got {:?}", pets, loaded);
+        }
+
+        fs::remove_dir_all(dir)?;
+
+        Ok(())
+    }
+}
@@ -1,12 +1,12 @@
-#![allow(clippy::module_name_repetitions)]
+#![allow(clippy::module_name_repetitions, clippy::missing_trait_methods)]
 
 //!
 
-use core::str::FromStr;
+use core::{mem, str::FromStr};
 
 use anyhow::{anyhow, bail};
 
-use crate::value::Value;
+use crate::{state::ROOT_PATH, value::Value};
 
 ///
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -27,9 +27,11 @@ impl ValuePointer {
             &Value::Object(ref obj) => obj
                 .get(next)
                 .ok_or_else(|| anyhow!("Key {} not found", next)),
-            &(Value::Null | Value::Bool(_) | Value::String(_) | Value::Number(_)) => {
-                Err(anyhow!("Cannot index into a non-object or array value"))
-            }
+            &(Value::Null
+            | Value::Bool(_)
+            | Value::String(_)
+            | Value::Number(_)
+            | Value::Bytes(_)) => Err(anyhow!("Cannot index into a non-object or array value")),
         })
     }
 
@@ -44,11 +46,153 @@ impl ValuePointer {
             &mut Value::Object(ref mut obj) => obj
                 .get_mut(next)
                 .ok_or_else(|| anyhow!("Key {} not found", next)),
-            &mut (Value::Null | Value::Bool(_) | Value::String(_) | Value::Number(_)) => {
-                Err(anyhow!("Cannot index into a non-object or array value"))
-            }
+            &mut (Value::Null
+            | Value::Bool(_)
+            | Value::String(_)
+            | Value::Number(_)
+            | Value::Bytes(_)) => Err(anyhow!("Cannot index into a non-object or array value")),
         })
     }
+
+    /// Puts `value` at this pointer and returns the value it replaced. A new key
+    /// is added to an object, and `-` appends to an array.
+    pub fn set(&self, root: &mut Value, value: Value) -> anyhow::Result<Option<Value>> {
+        let Some((last, parent)) = self.tokens.split_last() else {
+            return Ok(Some(mem::replace(root, value)));
+        };
+
+        match Self::from_tokens(parent).get_mut(root)? {
+            &mut Value::Array(ref mut arr) => {
+                if last == "-" {
+                    arr.push(value);
+                    return Ok(None);
+                }
+
+                let idx = last.parse::<usize>()?;
+                let slot = arr
+                    .get_mut(idx)
+                    .ok_or_else(|| anyhow!("Index {} out of bounds", idx))?;
+                Ok(Some(mem::replace(slot, value)))
+            }
+            &mut Value::Object(ref mut obj) => Ok(obj.insert(last.clone(), value)),
+            &mut (Value::Null
+            | Value::Bool(_)
+            | Value::String(_)
+            | Value::Number(_)
+            | Value::Bytes(_)) => Err(anyhow!("Cannot index into a non-object or array value")),
+        }
+    }
+
+    /// Takes the value at this pointer out of the document, keeping the order
+    /// of what is left.
+    pub fn remove(&self, root: &mut Value) -> anyhow::Result<Value> {
+        let Some((last, parent)) = self.tokens.split_last() else {
+            bail!("Cannot remove the root of the document");
+        };
+
+        match Self::from_tokens(parent).get_mut(root)? {
+            &mut Value::Array(ref mut arr) => {
+                let idx = last.parse::<usize>()?;
+                if idx >= arr.len() {
+                    bail!("Index {} out of bounds", idx);
+                }
+                Ok(arr.remove(idx))
+            }
+            &mut Value::Object(ref mut obj) => obj
+                .shift_remove(last)
+                .ok_or_else(|| anyhow!("Key {} not found", last)),
+            &mut (Value::Null
+            | Value::Bool(_)
+            | Value::String(_)
+            | Value::Number(_)
+            | Value::Bytes(_)) => Err(anyhow!("Cannot index into a non-object or array value")),
+        }
+    }
+
+    ///
+    fn from_tokens(tokens: &[String]) -> Self {
+        Self {
+            tokens: tokens.to_vec(),
+        }
+    }
+}
+
+/// Walks every node of a document depth first, in document order, along with
+/// its pointer and depth (the root is at depth 0).
+pub struct Walk<'value> {
+    ///
+    stack: Vec<(String, &'value Value, usize)>,
+
+    /// Don't descend into `$ref` maps.
+    skip_refs: bool,
+
+    ///
+    max_depth: Option<usize>,
+}
+
+impl<'value> Walk<'value> {
+    ///
+    pub fn new(root: &'value Value) -> Self {
+        Self {
+            stack: vec![(ROOT_PATH.to_owned(), root, 0)],
+            skip_refs: false,
+            max_depth: None,
+        }
+    }
+
+    /// Treat `$ref` maps as leaves, their target is walked where it lives.
+    pub const fn skip_refs(mut self) -> Self {
+        self.skip_refs = true;
+        self
+    }
+
+    /// Don't descend below `depth`.
+    pub const fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+}
+
+impl<'value> Iterator for Walk<'value> {
+    type Item = (String, &'value Value, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, value, depth) = self.stack.pop()?;
+        if self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return Some((path, value, depth));
+        }
+
+        let child_depth = depth.saturating_add(1);
+        match value {
+            &Value::Object(ref map) => {
+                if !(self.skip_refs && map.contains_key("$ref")) {
+                    for (key, child) in map.iter().rev() {
+                        let key = escape(key);
+                        self.stack
+                            .push((format!("{path}/{key}"), child, child_depth));
+                    }
+                }
+            }
+            &Value::Array(ref array) => {
+                for (index, child) in array.iter().enumerate().rev() {
+                    self.stack
+                        .push((format!("{path}/{index}"), child, child_depth));
+                }
+            }
+            &Value::Null
+            | &Value::Bool(_)
+            | &Value::Number(_)
+            | &Value::Bytes(_)
+            | &Value::String(_) => {}
+        }
+
+        Some((path, value, depth))
+    }
+}
+
+/// Escapes a key for use as a pointer token.
+pub fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
 }
 
 impl FromStr for ValuePointer {
@@ -90,3 +234,107 @@ impl FromStr for ValuePointer {
         Ok(ValuePointer { tokens })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_set() -> anyhow::Result<()> {
+        let mut doc: Value = serde_yaml::from_str("info:\n  version: 1.0.0\ntags: [a]\n")?;
+
+        let cases = [
+            ("#/info/version", "2.0.0", Some("1.0.0")),
+            ("#/info/title", "Pets", None),
+            ("#/tags/0", "b", Some("a")),
+            ("#/tags/-", "c", None),
+        ];
+
+        for (pointer, value, expected) in cases {
+            let pointer = pointer.parse::<ValuePointer>()?;
+            let replaced = pointer.set(&mut doc, Value::String(value.to_owned()))?;
+            let expected = expected.map(|expected| Value::String(expected.to_owned()));
+            if replaced != expected {
+                bail!("Expected {:?}, got {:?}", expected, replaced);
+            }
+        }
+
+        let expected: Value =
+            serde_yaml::from_str("info:\n  version: 2.0.0\n  title: Pets\ntags: [b, c]\n")?;
+        if doc != expected {
+            bail!("Expected {:?}, got {:?}", expected, doc);
+        }
+
+        if "#/missing/key"
+            .parse::<ValuePointer>()?
+            .set(&mut doc, Value::Null)
+            .is_ok()
+        {
+            bail!("Expected setting below a missing key to fail");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove() -> anyhow::Result<()> {
+        let mut doc: Value =
+            serde_yaml::from_str("paths:\n  /legacy: 1\n  /pets: 2\ntags: [a, b]\n")?;
+
+        for pointer in ["#/paths/~1legacy", "#/tags/0"] {
+            pointer.parse::<ValuePointer>()?.remove(&mut doc)?;
+        }
+
+        let expected: Value = serde_yaml::from_str("paths:\n  /pets: 2\ntags: [b]\n")?;
+        if doc != expected {
+            bail!("Expected {:?}, got {:?}", expected, doc);
+        }
+
+        for pointer in ["#", "#/tags/1", "#/paths/~1legacy"] {
+            if pointer.parse::<ValuePointer>()?.remove(&mut doc).is_ok() {
+                bail!("Expected removing {:?} to fail", pointer);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_walk() -> anyhow::Result<()> {
+        let doc: Value =
+            serde_yaml::from_str("b:\n  ref: {$ref: '#/a'}\n  list: [1]\na:\n  a/b~c: true\n")?;
+
+        let paths = Walk::new(&doc)
+            .map(|(path, _, depth)| format!("{path}@{depth}"))
+            .collect::<Vec<_>>();
+        let expected = [
+            "#@0",
+            "#/b@1",
+            "#/b/ref@2",
+            "#/b/ref/$ref@3",
+            "#/b/list@2",
+            "#/b/list/0@3",
+            "#/a@1",
+            "#/a/a~1b~0c@2",
+        ];
+        if paths != expected {
+            bail!("Expected {:?}, got {:?}", expected, paths);
+        }
+
+        let paths = Walk::new(&doc)
+            .skip_refs()
+            .max_depth(2)
+            .map(|(path, _, _)| path)
+            .collect::<Vec<_>>();
+        let expected = ["#", "#/b", "#/b/ref", "#/b/list", "#/a", "#/a/a~1b~0c"];
+        if paths != expected {
+            bail!("Expected {:?}, got {:?}", expected, paths);
+        }
+
+        for (path, _, _) in Walk::new(&doc) {
+            path.parse::<ValuePointer>()?.get(&doc)?;
+        }
+
+        Ok(())
+    }
+}
@@ -2,18 +2,55 @@
 
 //!
 
-use std::collections::HashMap;
+use core::mem;
 
 use crate::{
     action::Action,
-    pointer::ValuePointer,
-    search::search,
+    diagnostics,
+    pointer::{escape, ValuePointer},
+    reference,
+    search::{index, references, search, usages},
     state::{self, State, Step, ROOT_PATH},
     value::Value,
 };
 
 ///
 pub fn reducer(mut state: State, action: Action) -> State {
+    if !action.mutates_document() {
+        return reduce(state, action);
+    }
+
+    // Every change to the document flags it for reindexing, so start from a cleared flag
+    // to tell whether the action made one or was refused
+    let needs_index = mem::take(&mut state.needs_index);
+    let mut state = reduce(state, action);
+    if state.needs_index {
+        state.problems = diagnostics::check(&state.doc);
+        state.problems_state.selected = state
+            .problems_state
+            .selected
+            .min(state.problems.len().saturating_sub(1));
+    }
+    state.needs_index |= needs_index;
+
+    state
+}
+
+///
+fn reduce(mut state: State, action: Action) -> State {
+    if state.read_only && action.mutates_document() {
+        state.status.message = state::StatusMessage::Warn(state::READ_ONLY_WARNING.to_owned());
+        state.status.timeout = Some(state.config.status_timeout())
+            .and_then(|dur| std::time::Instant::now().checked_add(dur));
+        return state;
+    }
+    if state.nav_state.current.file.is_some() && action.mutates_document() {
+        state.status.message = state::StatusMessage::Warn(state::EXTERNAL_WARNING.to_owned());
+        state.status.timeout = Some(state.config.status_timeout())
+            .and_then(|dur| std::time::Instant::now().checked_add(dur));
+        return state;
+    }
+
     match action {
         Action::SetCurrentPage { page } => {
             state.current_page = page;
@@ -33,27 +70,45 @@ pub fn reducer(mut state: State, action: Action) -> State {
             if let Some(selected) = selected_path
                 .parse::<ValuePointer>()
                 .ok()
-                .and_then(|pointer| pointer.get(&state.doc).ok())
+                .and_then(|pointer| pointer.get(state.current_doc()).ok())
             {
                 let index = state.nav_state.current.selected;
                 let path = match selected {
                     &Value::Object(ref map) => map.get_index(index).map(|(key, value)| {
                         if let &Value::Object(ref value) = value {
                             if let Some(&Value::String(ref reference)) = value.get("$ref") {
-                                return reference.clone();
+                                return (reference.clone(), true);
                             }
                         }
 
-                        let key = key.replace('/', "~1");
-
-                        format!("{selected_path}/{key}")
+                        (format!("{selected_path}/{}", escape(key)), false)
                     }),
-                    &Value::Array(_) => Some(format!("{selected_path}/{index}")),
-                    &Value::Null | &Value::Bool(_) | &Value::Number(_) | &Value::String(_) => None,
+                    &Value::Array(_) => Some((format!("{selected_path}/{index}"), false)),
+                    &Value::Null
+                    | &Value::Bool(_)
+                    | &Value::Number(_)
+                    | &Value::Bytes(_)
+                    | &Value::String(_) => None,
                 };
 
-                if let Some(path) = path {
-                    let mut step = Step { path, selected: 0 };
+                if let Some((path, reference)) = path {
+                    if reference {
+                        // `$ref`s into other files are loaded and followed with `NavFollow`
+                        let (file, pointer) = reference::split(&path);
+                        if !file.is_empty() {
+                            return state;
+                        }
+                        if pointer != path {
+                            return state;
+                        }
+                        record_jump(&mut state);
+                    }
+
+                    let mut step = Step {
+                        file: state.nav_state.current.file.clone(),
+                        path,
+                        selected: 0,
+                    };
 
                     core::mem::swap(&mut state.nav_state.current, &mut step);
                     state.nav_state.history.push(step);
@@ -69,11 +124,15 @@ pub fn reducer(mut state: State, action: Action) -> State {
                 .path
                 .parse::<ValuePointer>()
                 .ok()
-                .and_then(|pointer| pointer.get(&state.doc).ok())
+                .and_then(|pointer| pointer.get(state.current_doc()).ok())
                 .map_or(0, |value| match value {
                     &Value::Object(ref map) => map.len(),
                     &Value::Array(ref array) => array.len(),
-                    &Value::Null | &Value::Bool(_) | &Value::Number(_) | &Value::String(_) => 0,
+                    &Value::Null
+                    | &Value::Bool(_)
+                    | &Value::Number(_)
+                    | &Value::Bytes(_)
+                    | &Value::String(_) => 0,
                 })
                 .saturating_sub(1);
 
@@ -100,7 +159,11 @@ pub fn reducer(mut state: State, action: Action) -> State {
                 let option_count = match previous {
                     &mut Value::Object(ref map) => map.len(),
                     &mut Value::Array(ref array) => array.len(),
-                    &mut (Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_)) => 0,
+                    &mut (Value::Null
+                    | Value::Bool(_)
+                    | Value::Number(_)
+                    | Value::Bytes(_)
+                    | Value::String(_)) => 0,
                 }
                 .saturating_sub(1);
 
@@ -128,7 +191,11 @@ pub fn reducer(mut state: State, action: Action) -> State {
                         });
                         state.redo_stack.clear();
                     }
-                    &mut (Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_)) => {}
+                    &mut (Value::Null
+                    | Value::Bool(_)
+                    | Value::Number(_)
+                    | Value::Bytes(_)
+                    | Value::String(_)) => {}
                 }
 
                 state.nav_state.current.selected = new_selected;
@@ -143,11 +210,15 @@ pub fn reducer(mut state: State, action: Action) -> State {
                 .path
                 .parse::<ValuePointer>()
                 .ok()
-                .and_then(|pointer| pointer.get(&state.doc).ok())
+                .and_then(|pointer| pointer.get(state.current_doc()).ok())
                 .map_or(0, |value| match value {
                     &Value::Object(ref map) => map.len(),
                     &Value::Array(ref array) => array.len(),
-                    &Value::Null | &Value::Bool(_) | &Value::Number(_) | &Value::String(_) => 0,
+                    &Value::Null
+                    | &Value::Bool(_)
+                    | &Value::Number(_)
+                    | &Value::Bytes(_)
+                    | &Value::String(_) => 0,
                 });
 
             let new_selected = state
@@ -174,7 +245,11 @@ pub fn reducer(mut state: State, action: Action) -> State {
                 let option_count = match previous {
                     &mut Value::Object(ref map) => map.len(),
                     &mut Value::Array(ref array) => array.len(),
-                    &mut (Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_)) => 0,
+                    &mut (Value::Null
+                    | Value::Bool(_)
+                    | Value::Number(_)
+                    | Value::Bytes(_)
+                    | Value::String(_)) => 0,
                 };
 
                 let cur = state.nav_state.current.selected;
@@ -204,7 +279,11 @@ pub fn reducer(mut state: State, action: Action) -> State {
                         });
                         state.redo_stack.clear();
                     }
-                    &mut (Value::Null | Value::Bool(_) | Value::String(_) | Value::Number(_)) => {}
+                    &mut (Value::Null
+                    | Value::Bool(_)
+                    | Value::String(_)
+                    | Value::Number(_)
+                    | Value::Bytes(_)) => {}
                 }
 
                 state.nav_state.current.selected = new_selected;
@@ -223,11 +302,15 @@ pub fn reducer(mut state: State, action: Action) -> State {
                 .path
                 .parse::<ValuePointer>()
                 .ok()
-                .and_then(|pointer| pointer.get(&state.doc).ok())
+                .and_then(|pointer| pointer.get(state.current_doc()).ok())
                 .map_or(0, |value| match value {
                     &Value::Object(ref map) => map.len(),
                     &Value::Array(ref array) => array.len(),
-                    &Value::Null | &Value::Bool(_) | &Value::Number(_) | &Value::String(_) => 0,
+                    &Value::Null
+                    | &Value::Bool(_)
+                    | &Value::Number(_)
+                    | &Value::Bytes(_)
+                    | &Value::String(_) => 0,
                 });
 
             state.nav_state.current.selected = option_count.saturating_sub(1);
@@ -235,64 +318,52 @@ pub fn reducer(mut state: State, action: Action) -> State {
             state
         }
         Action::NavGoto { path } => {
-            let parts: Vec<_> = path
-                .strip_prefix(ROOT_PATH)
-                .unwrap_or(&path)
-                .split('/')
-                .filter(|part| !part.is_empty())
-                .collect();
-
-            let mut history = Vec::with_capacity(parts.len());
-            let mut current = Step {
-                path: state::ROOT_PATH.to_owned(),
+            record_jump(&mut state);
+            goto(&mut state, None, &path);
+
+            state
+        }
+        Action::NavFollow { file, path } => {
+            record_jump(&mut state);
+
+            let mut step = Step {
+                file,
+                path,
                 selected: 0,
             };
-            let mut current_path = ROOT_PATH.to_owned();
-            let mut current_value = &state.doc;
+            core::mem::swap(&mut state.nav_state.current, &mut step);
+            state.nav_state.history.push(step);
 
-            for part in parts {
-                if !current_path.is_empty() {
-                    current_path.push('/');
-                }
-                current_path.push_str(part);
-
-                let child = match current_value {
-                    &Value::Array(ref array) => {
-                        let index = part.parse::<usize>().unwrap_or_default();
-                        array.get(index).map(|v| (index, v))
-                    }
-                    &Value::Object(ref obj) => {
-                        let key = part.replace("~1", "/");
-                        obj.get_full(&key).map(|(idx, _, value)| (idx, value))
-                    }
-                    &Value::Null | &Value::Bool(_) | &Value::String(_) | &Value::Number(_) => None,
-                };
+            state
+        }
+        Action::ExternalLoad { file, doc } => {
+            state.external.insert(file, doc);
+            state
+        }
+        Action::JumpBack => {
+            let index = state.nav_state.jump_index;
+            if index == 0 {
+                return state;
+            }
 
-                current_value = if let Some((index, child)) = child {
-                    current.selected = index;
-                    match child {
-                        &(Value::Array(_) | Value::Object(_)) => {
-                            let mut next_step = Step {
-                                path: current_path.clone(),
-                                selected: 0,
-                            };
+            // Remember where the jumps started, to come back with `JumpForward`
+            if index >= state.nav_state.jumps.len() {
+                state.nav_state.jumps.push(state.nav_state.current.clone());
+            }
 
-                            core::mem::swap(&mut current, &mut next_step);
+            state.nav_state.jump_index = index.saturating_sub(1);
+            restore_jump(&mut state);
 
-                            history.push(next_step);
-                            child
-                        }
-                        &(Value::Null | Value::Bool(_) | Value::String(_) | Value::Number(_)) => {
-                            break;
-                        }
-                    }
-                } else {
-                    break;
-                };
+            state
+        }
+        Action::JumpForward => {
+            let index = state.nav_state.jump_index.saturating_add(1);
+            if index >= state.nav_state.jumps.len() {
+                return state;
             }
 
-            state.nav_state.current = current;
-            state.nav_state.history = history;
+            state.nav_state.jump_index = index;
+            restore_jump(&mut state);
 
             state
         }
@@ -360,9 +431,11 @@ pub fn reducer(mut state: State, action: Action) -> State {
                             )
                         })
                     }
-                    &mut (Value::Null | Value::Bool(_) | Value::String(_) | Value::Number(_)) => {
-                        None
-                    }
+                    &mut (Value::Null
+                    | Value::Bool(_)
+                    | Value::String(_)
+                    | Value::Number(_)
+                    | Value::Bytes(_)) => None,
                 };
 
                 if let Some((path, existing)) = existing {
@@ -391,6 +464,168 @@ pub fn reducer(mut state: State, action: Action) -> State {
         }
         // Action::ExportPromptCursorLeft
         // Action::ExportPromptCursorRight
+        Action::SaveAsPromptSetValue { value } => {
+            state.save_as_prompt_state.value = value;
+            state
+        }
+        Action::ConfirmOverwrite { file_name } => {
+            state.save_as_prompt_state.overwrite = Some(file_name);
+            state
+        }
+        Action::CommandLineSetValue { value } => {
+            state.command_line_state.value = value;
+            state.command_line_state.history_index = None;
+            state
+        }
+        Action::CommandLineRecord { line } => {
+            let command_line = &mut state.command_line_state;
+            if !line.trim().is_empty() && command_line.history.last() != Some(&line) {
+                command_line.history.push(line);
+            }
+            command_line.value.clear();
+            command_line.history_index = None;
+            state
+        }
+        Action::CommandLineHistoryUp => {
+            let command_line = &mut state.command_line_state;
+            let index = match command_line.history_index {
+                Some(index) => index.saturating_sub(1),
+                None => command_line.history.len().saturating_sub(1),
+            };
+            if let Some(line) = command_line.history.get(index) {
+                command_line.value = line.clone();
+                command_line.history_index = Some(index);
+            }
+            state
+        }
+        Action::CommandLineHistoryDown => {
+            let command_line = &mut state.command_line_state;
+            if let Some(index) = command_line.history_index {
+                let index = index.saturating_add(1);
+                command_line.value = command_line.history.get(index).cloned().unwrap_or_default();
+                command_line.history_index = (index < command_line.history.len()).then_some(index);
+            }
+            state
+        }
+        Action::SetMark { mark, path } => {
+            state.marks.insert(mark, path);
+            state
+        }
+        Action::DeleteMark => {
+            let mark = state.marks.keys().nth(state.marks_state.selected).copied();
+            if let Some(mark) = mark {
+                state.marks.remove(&mark);
+            }
+            state.marks_state.selected = state
+                .marks_state
+                .selected
+                .min(state.marks.len().saturating_sub(1));
+            state
+        }
+        Action::MarksUp => {
+            state.marks_state.selected = state
+                .marks_state
+                .selected
+                .checked_sub(1)
+                .unwrap_or(state.marks.len().saturating_sub(1));
+            state
+        }
+        Action::MarksDown => {
+            state.marks_state.selected = state
+                .marks_state
+                .selected
+                .wrapping_add(1)
+                .checked_rem_euclid(state.marks.len())
+                .unwrap_or_default();
+            state
+        }
+        Action::ProblemsUp => {
+            state.problems_state.selected = state
+                .problems_state
+                .selected
+                .checked_sub(1)
+                .unwrap_or(state.problems.len().saturating_sub(1));
+            state
+        }
+        Action::ProblemsDown => {
+            state.problems_state.selected = state
+                .problems_state
+                .selected
+                .wrapping_add(1)
+                .checked_rem_euclid(state.problems.len())
+                .unwrap_or_default();
+            state
+        }
+        Action::SortCurrent => {
+            let path = state.nav_state.current.path.clone();
+            let Some(existing) = path
+                .parse::<ValuePointer>()
+                .ok()
+                .and_then(|pointer| pointer.get_mut(&mut state.doc).ok())
+            else {
+                return state;
+            };
+
+            let Some(sorted) = existing.sorted() else {
+                state.status.message = state::StatusMessage::Warn(
+                    "Only objects and arrays of strings, numbers or booleans can be sorted"
+                        .to_owned(),
+                );
+                state.status.timeout = Some(state.config.status_timeout())
+                    .and_then(|dur| std::time::Instant::now().checked_add(dur));
+                return state;
+            };
+
+            // Keep the same entry of an object highlighted
+            let selected = state.nav_state.current.selected;
+            if let (&mut Value::Object(ref map), &Value::Object(ref sorted_map)) =
+                (&mut *existing, &sorted)
+            {
+                if let Some(index) = map
+                    .get_index(selected)
+                    .and_then(|(key, _)| sorted_map.get_index_of(key))
+                {
+                    state.nav_state.current.selected = index;
+                }
+            }
+
+            state.needs_index = true;
+            state.undo_stack.push(state::UndoAction::ReplaceCurrent {
+                path: path.clone(),
+                value: core::mem::replace(existing, sorted),
+            });
+            state.redo_stack.clear();
+
+            state.status.message = state::StatusMessage::Ok(format!("Sorted {path}"));
+            state.status.timeout = Some(state.config.status_timeout())
+                .and_then(|dur| std::time::Instant::now().checked_add(dur));
+            state
+        }
+        Action::SetReadOnly { read_only } => {
+            state.read_only = read_only;
+            state
+        }
+        Action::SetFile {
+            file_name,
+            persisted,
+        } => {
+            state.file_name = file_name;
+            state.persisted = Some(persisted);
+            state.save_as_prompt_state.overwrite = None;
+            state
+        }
+        Action::Pick { path } => {
+            state.picked = Some(path);
+            state
+        }
+        Action::DiscardComments => {
+            // Without its original text the document is written from scratch
+            if let Some(ref mut persisted) = state.persisted {
+                persisted.comments = false;
+                persisted.source = None;
+            }
+            state
+        }
         Action::SetStatus { message, timeout } => {
             state.status.message = message;
             state.status.timeout =
@@ -398,39 +633,32 @@ pub fn reducer(mut state: State, action: Action) -> State {
             state
         }
         Action::SearchSetAllPaths => {
-            if state.needs_index {
-                log::info!("Changes exist on document, reindexing paths");
-                let mut stack = vec![(ROOT_PATH.to_owned(), &state.doc)];
-                let mut paths = HashMap::new();
-
-                while let Some((path, value)) = stack.pop() {
-                    match value {
-                        &Value::Object(ref map) => {
-                            if !map.contains_key("$ref") {
-                                for (key, value) in map {
-                                    let key = key.replace('/', "~1");
-                                    let path = format!("{path}/{key}");
-                                    stack.push((path, value));
-                                }
-                            }
-                        }
-                        &Value::Array(ref array) => {
-                            for (index, value) in array.iter().enumerate() {
-                                let path = format!("{path}/{index}");
-                                stack.push((path, value));
-                            }
-                        }
-                        &Value::Null | &Value::Bool(_) | &Value::Number(_) | &Value::String(_) => {}
-                    };
-
-                    paths.insert(path, 0);
-                }
-
-                state.search_state.cache.reset(paths.clone());
-                state.search_state.deref_cache.reset(paths);
-                state.needs_index = false;
-            }
+            reindex(&mut state);
+            state
+        }
+        Action::FindUsages { path } => {
+            reindex(&mut state);
 
+            state.usages_state.results = usages(&state.usages_state.references, &path);
+            state.usages_state.path = path;
+            state.usages_state.selected = 0;
+            state
+        }
+        Action::UsagesUp => {
+            state.usages_state.selected = state
+                .usages_state
+                .selected
+                .checked_sub(1)
+                .unwrap_or(state.usages_state.results.len().saturating_sub(1));
+            state
+        }
+        Action::UsagesDown => {
+            state.usages_state.selected = state
+                .usages_state
+                .selected
+                .wrapping_add(1)
+                .checked_rem_euclid(state.usages_state.results.len())
+                .unwrap_or_default();
             state
         }
         Action::Undo => {
@@ -459,7 +687,7 @@ pub fn reducer(mut state: State, action: Action) -> State {
                             state.status.message = state::StatusMessage::Ok(format!(
                                 "Successful undo value replacement at {path}"
                             ));
-                            state.status.timeout = Some(core::time::Duration::from_secs(2))
+                            state.status.timeout = Some(state.config.status_timeout())
                                 .and_then(|dur| std::time::Instant::now().checked_add(dur));
                         } else {
                             log::error!("Corrupted undo stack, try reloading the document");
@@ -480,7 +708,7 @@ pub fn reducer(mut state: State, action: Action) -> State {
                             match node {
                                 &mut Value::Array(ref mut arr) => {
                                     for step in &mut state.nav_state.history {
-                                        if step.path == path {
+                                        if step.file.is_none() && step.path == path {
                                             if step.selected == from {
                                                 step.selected = to;
                                             } else if step.selected == to {
@@ -502,12 +730,12 @@ pub fn reducer(mut state: State, action: Action) -> State {
                                     state.status.message = state::StatusMessage::Ok(format!(
                                         "Successful undo array move at {path}"
                                     ));
-                                    state.status.timeout = Some(core::time::Duration::from_secs(2))
+                                    state.status.timeout = Some(state.config.status_timeout())
                                         .and_then(|dur| std::time::Instant::now().checked_add(dur));
                                 }
                                 &mut Value::Object(ref mut obj) => {
                                     for step in &mut state.nav_state.history {
-                                        if step.path == path {
+                                        if step.file.is_none() && step.path == path {
                                             if step.selected == from {
                                                 step.selected = to;
                                             } else if step.selected == to {
@@ -529,12 +757,13 @@ pub fn reducer(mut state: State, action: Action) -> State {
                                     state.status.message = state::StatusMessage::Ok(format!(
                                         "Successful undo object move at {path}"
                                     ));
-                                    state.status.timeout = Some(core::time::Duration::from_secs(2))
+                                    state.status.timeout = Some(state.config.status_timeout())
                                         .and_then(|dur| std::time::Instant::now().checked_add(dur));
                                 }
                                 &mut (Value::Null
                                 | Value::Bool(_)
                                 | Value::Number(_)
+                                | Value::Bytes(_)
                                 | Value::String(_)) => {
                                     log::error!("Corrupted undo stack, try reloading the document");
                                     state.undo_stack.clear();
@@ -559,7 +788,7 @@ pub fn reducer(mut state: State, action: Action) -> State {
                 }
             } else {
                 state.status.message = state::StatusMessage::Warn("Nothing to undo".to_owned());
-                state.status.timeout = Some(core::time::Duration::from_secs(2))
+                state.status.timeout = Some(state.config.status_timeout())
                     .and_then(|dur| std::time::Instant::now().checked_add(dur));
             }
 
@@ -591,7 +820,7 @@ pub fn reducer(mut state: State, action: Action) -> State {
                             state.status.message = state::StatusMessage::Ok(format!(
                                 "Successful redo value replacement at {path}"
                             ));
-                            state.status.timeout = Some(core::time::Duration::from_secs(2))
+                            state.status.timeout = Some(state.config.status_timeout())
                                 .and_then(|dur| std::time::Instant::now().checked_add(dur));
                         } else {
                             log::error!("Corrupted redo stack, try reloading the document");
@@ -612,7 +841,7 @@ pub fn reducer(mut state: State, action: Action) -> State {
                             match node {
                                 &mut Value::Array(ref mut arr) => {
                                     for step in &mut state.nav_state.history {
-                                        if step.path == path {
+                                        if step.file.is_none() && step.path == path {
                                             if step.selected == from {
                                                 step.selected = to;
                                             } else if step.selected == to {
@@ -634,12 +863,12 @@ pub fn reducer(mut state: State, action: Action) -> State {
                                     state.status.message = state::StatusMessage::Ok(format!(
                                         "Successful redo array move at {path}"
                                     ));
-                                    state.status.timeout = Some(core::time::Duration::from_secs(2))
+                                    state.status.timeout = Some(state.config.status_timeout())
                                         .and_then(|dur| std::time::Instant::now().checked_add(dur));
                                 }
                                 &mut Value::Object(ref mut obj) => {
                                     for step in &mut state.nav_state.history {
-                                        if step.path == path {
+                                        if step.file.is_none() && step.path == path {
                                             if step.selected == from {
                                                 step.selected = to;
                                             } else if step.selected == to {
@@ -661,12 +890,13 @@ pub fn reducer(mut state: State, action: Action) -> State {
                                     state.status.message = state::StatusMessage::Ok(format!(
                                         "Successful redo object move at {path}"
                                     ));
-                                    state.status.timeout = Some(core::time::Duration::from_secs(2))
+                                    state.status.timeout = Some(state.config.status_timeout())
                                         .and_then(|dur| std::time::Instant::now().checked_add(dur));
                                 }
                                 &mut (Value::Null
                                 | Value::Bool(_)
                                 | Value::Number(_)
+                                | Value::Bytes(_)
                                 | Value::String(_)) => {
                                     log::error!("Corrupted redo stack, try reloading the document");
                                     state.undo_stack.clear();
@@ -691,7 +921,7 @@ pub fn reducer(mut state: State, action: Action) -> State {
                 }
             } else {
                 state.status.message = state::StatusMessage::Warn("Nothing to redo".to_owned());
-                state.status.timeout = Some(core::time::Duration::from_secs(2))
+                state.status.timeout = Some(state.config.status_timeout())
                     .and_then(|dur| std::time::Instant::now().checked_add(dur));
             }
 
@@ -705,3 +935,438 @@ pub fn reducer(mut state: State, action: Action) -> State {
         }
     }
 }
+
+/// Rebuilds the search index and the `$ref`s into the document after it changed.
+fn reindex(state: &mut State) {
+    if state.needs_index {
+        log::info!("Changes exist on document, reindexing paths");
+        let paths = index(&state.doc);
+
+        state.search_state.cache.reset(paths.clone());
+        state.search_state.deref_cache.reset(paths);
+        state.usages_state.references = references(&state.doc);
+        state.needs_index = false;
+    }
+}
+
+/// Moves to the value at `path` of `file`, or as close to it as the document goes.
+fn goto(state: &mut State, file: Option<&str>, path: &str) {
+    let parts: Vec<_> = path
+        .strip_prefix(ROOT_PATH)
+        .unwrap_or(path)
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    let mut history = Vec::with_capacity(parts.len());
+    let mut current = Step {
+        file: file.map(str::to_owned),
+        path: state::ROOT_PATH.to_owned(),
+        selected: 0,
+    };
+    let mut current_path = ROOT_PATH.to_owned();
+    let mut current_value = state.document(file);
+
+    for part in parts {
+        if !current_path.is_empty() {
+            current_path.push('/');
+        }
+        current_path.push_str(part);
+
+        let child = match current_value {
+            &Value::Array(ref array) => {
+                let index = part.parse::<usize>().unwrap_or_default();
+                array.get(index).map(|v| (index, v))
+            }
+            &Value::Object(ref obj) => {
+                let key = part.replace("~1", "/").replace("~0", "~");
+                obj.get_full(&key).map(|(idx, _, value)| (idx, value))
+            }
+            &Value::Null
+            | &Value::Bool(_)
+            | &Value::String(_)
+            | &Value::Number(_)
+            | &Value::Bytes(_) => None,
+        };
+
+        current_value = if let Some((index, child)) = child {
+            current.selected = index;
+            match child {
+                &(Value::Array(_) | Value::Object(_)) => {
+                    let mut next_step = Step {
+                        file: file.map(str::to_owned),
+                        path: current_path.clone(),
+                        selected: 0,
+                    };
+
+                    core::mem::swap(&mut current, &mut next_step);
+
+                    history.push(next_step);
+                    child
+                }
+                &(Value::Null
+                | Value::Bool(_)
+                | Value::String(_)
+                | Value::Number(_)
+                | Value::Bytes(_)) => {
+                    break;
+                }
+            }
+        } else {
+            break;
+        };
+    }
+
+    state.nav_state.current = current;
+    state.nav_state.history = history;
+}
+
+/// Adds the current location to the jump list before jumping elsewhere, dropping the
+/// locations jumped back over.
+fn record_jump(state: &mut State) {
+    let nav = &mut state.nav_state;
+    nav.jumps.truncate(nav.jump_index);
+    if nav.jumps.last() != Some(&nav.current) {
+        nav.jumps.push(nav.current.clone());
+    }
+    if nav.jumps.len() > state::MAX_JUMPS {
+        nav.jumps.remove(0);
+    }
+    nav.jump_index = nav.jumps.len();
+}
+
+/// Moves to the location at `jump_index` in the jump list.
+fn restore_jump(state: &mut State) {
+    let Some(step) = state
+        .nav_state
+        .jumps
+        .get(state.nav_state.jump_index)
+        .cloned()
+    else {
+        return;
+    };
+
+    goto(state, step.file.as_deref(), &step.path);
+    // The document may have changed since, keep the selection within the current items
+    let current = &state.nav_state.current;
+    if current.file == step.file && current.path == step.path {
+        let len = step
+            .path
+            .parse::<ValuePointer>()
+            .ok()
+            .and_then(|pointer| pointer.get(state.current_doc()).ok())
+            .map_or(0, |value| match value {
+                &Value::Object(ref map) => map.len(),
+                &Value::Array(ref array) => array.len(),
+                &Value::Null
+                | &Value::Bool(_)
+                | &Value::Number(_)
+                | &Value::Bytes(_)
+                | &Value::String(_) => 0,
+            });
+        state.nav_state.current.selected = step.selected.min(len.saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::bail;
+
+    use super::*;
+    use crate::state::test::state;
+
+    #[test]
+    fn test_read_only() -> anyhow::Result<()> {
+        let doc: Value = serde_yaml::from_str("a: 1\nb: 2\n")?;
+        let mut state = state(doc.clone());
+        state.read_only = true;
+
+        let actions = [
+            Action::NavMoveDown,
+            Action::DocumentReplaceCurrent { value: Value::Null },
+            Action::Undo,
+        ];
+        for action in actions {
+            state = reducer(state, action);
+            if state.doc != doc {
+                bail!("Expected {:?}, got {:?}", doc, state.doc);
+            }
+            if !matches!(state.status.message, state::StatusMessage::Warn(ref message) if message == state::READ_ONLY_WARNING)
+            {
+                bail!("Expected a read only warning");
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_current() -> anyhow::Result<()> {
+        let doc: Value = serde_yaml::from_str("b: 1\nc: [3, 1, 2]\na: 2\n")?;
+        let mut state = state(doc);
+        state.nav_state.current.selected = 1;
+
+        state = reducer(state, Action::SortCurrent);
+        let expected: Value = serde_yaml::from_str("a: 2\nb: 1\nc: [3, 1, 2]\n")?;
+        if state.doc != expected {
+            bail!("Expected {:?}, got {:?}", expected, state.doc);
+        }
+        // `c` stays highlighted
+        if state.nav_state.current.selected != 2 {
+            bail!("Expected 2, got {}", state.nav_state.current.selected);
+        }
+
+        state = reducer(state, Action::NavSelect);
+        state = reducer(state, Action::SortCurrent);
+        let expected: Value = serde_yaml::from_str("a: 2\nb: 1\nc: [1, 2, 3]\n")?;
+        if state.doc != expected {
+            bail!("Expected {:?}, got {:?}", expected, state.doc);
+        }
+
+        if state.undo_stack.len() != 2 {
+            bail!("Expected 2 undo steps, got {}", state.undo_stack.len());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_mixed_array() -> anyhow::Result<()> {
+        let doc: Value = serde_yaml::from_str("[2, a, 1, true]")?;
+        let mut state = state(doc.clone());
+
+        state = reducer(state, Action::SortCurrent);
+        if state.doc != doc {
+            bail!("Expected {:?}, got {:?}", doc, state.doc);
+        }
+        if !matches!(state.status.message, state::StatusMessage::Warn(_)) {
+            bail!("Expected a warning");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_marks() -> anyhow::Result<()> {
+        let mut state = state(Value::Null);
+
+        let actions = [
+            Action::SetMark {
+                mark: 'b',
+                path: "#/b".to_owned(),
+            },
+            Action::SetMark {
+                mark: 'a',
+                path: "#/a".to_owned(),
+            },
+            Action::SetMark {
+                mark: 'b',
+                path: "#/b/0".to_owned(),
+            },
+            Action::MarksUp,
+        ];
+        for action in actions {
+            state = reducer(state, action);
+        }
+
+        let expected = [('a', "#/a".to_owned()), ('b', "#/b/0".to_owned())];
+        if !state
+            .marks
+            .iter()
+            .map(|(&mark, path)| (mark, path.clone()))
+            .eq(expected.clone())
+        {
+            bail!("Expected {:?}, got {:?}", expected, state.marks);
+        }
+        // Moving up from the first mark wraps to the last one
+        if state.marks_state.selected != 1 {
+            bail!("Expected 1, got {}", state.marks_state.selected);
+        }
+
+        state = reducer(state, Action::DeleteMark);
+        if state.marks.keys().ne(['a'].iter()) {
+            bail!("Expected only mark a, got {:?}", state.marks);
+        }
+        if state.marks_state.selected != 0 {
+            bail!("Expected 0, got {}", state.marks_state.selected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jump_list() -> anyhow::Result<()> {
+        let doc: Value =
+            serde_yaml::from_str("a:\n  ref: {$ref: '#/b/c'}\nb:\n  c: {x: 1}\n  d: [1, 2]\n")?;
+        let mut state = state(doc);
+        state.nav_state.current.selected = 1;
+
+        let cases = [
+            (
+                Action::NavGoto {
+                    path: "#/a".to_owned(),
+                },
+                "#/a",
+                0,
+            ),
+            // Follows the `$ref`
+            (Action::NavSelect, "#/b/c", 0),
+            (Action::JumpBack, "#/a", 0),
+            (Action::JumpBack, "#", 1),
+            (Action::JumpBack, "#", 1),
+            (Action::JumpForward, "#/a", 0),
+            (Action::JumpForward, "#/b/c", 0),
+            (Action::JumpForward, "#/b/c", 0),
+            (Action::JumpBack, "#/a", 0),
+            // A new jump drops the ones gone back over
+            (
+                Action::NavGoto {
+                    path: "#/b/d".to_owned(),
+                },
+                "#/b/d",
+                0,
+            ),
+            (Action::JumpForward, "#/b/d", 0),
+            (Action::JumpBack, "#/a", 0),
+            (Action::JumpBack, "#", 1),
+        ];
+
+        for (action, path, selected) in cases {
+            state = reducer(state, action);
+            let current = &state.nav_state.current;
+            if current.path != path || current.selected != selected {
+                bail!("Expected {:?} at {}, got {:?}", path, selected, current);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_external() -> anyhow::Result<()> {
+        let doc: Value = serde_yaml::from_str("user: {$ref: 'user.yaml#/User'}\n")?;
+        let user: Value =
+            serde_yaml::from_str("User:\n  id: {$ref: '#/Id'}\nId: {type: string}\n")?;
+        let mut state = state(doc.clone());
+        let file = Some("/specs/user.yaml".to_owned());
+
+        let actions = [
+            Action::ExternalLoad {
+                file: "/specs/user.yaml".to_owned(),
+                doc: user,
+            },
+            // Loading the file happens before, selecting alone doesn't follow it
+            Action::NavSelect,
+            Action::NavFollow {
+                file: file.clone(),
+                path: "#/User".to_owned(),
+            },
+            // A local `$ref` stays in the referenced file
+            Action::NavSelect,
+            Action::DocumentReplaceCurrent { value: Value::Null },
+        ];
+        for action in actions {
+            state = reducer(state, action);
+        }
+
+        let current = &state.nav_state.current;
+        if current.file != file || current.path != "#/Id" {
+            bail!("Expected #/Id in {:?}, got {:?}", file, current);
+        }
+        if state.doc != doc {
+            bail!("Expected {:?}, got {:?}", doc, state.doc);
+        }
+        if !matches!(state.status.message, state::StatusMessage::Warn(ref message) if message == state::EXTERNAL_WARNING)
+        {
+            bail!("Expected a warning about referenced files");
+        }
+
+        state = reducer(state, Action::NavBack);
+        state = reducer(state, Action::NavBack);
+        let current = &state.nav_state.current;
+        if current.file.is_some() || current.path != ROOT_PATH {
+            bail!("Expected to be back at the root, got {:?}", current);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_usages() -> anyhow::Result<()> {
+        let doc: Value =
+            serde_yaml::from_str("a: {$ref: '#/c'}\nb: {$ref: '#/c/d'}\nc:\n  d: 1\n")?;
+        let mut state = state(doc);
+
+        state = reducer(
+            state,
+            Action::FindUsages {
+                path: "#/c".to_owned(),
+            },
+        );
+        let expected = ["#/a", "#/b"];
+        if state.usages_state.results != expected {
+            bail!(
+                "Expected {:?}, got {:?}",
+                expected,
+                state.usages_state.results
+            );
+        }
+
+        // The index follows changes to the document
+        state.nav_state.current.selected = 1;
+        state = reducer(state, Action::DocumentReplaceCurrent { value: Value::Null });
+        state = reducer(
+            state,
+            Action::FindUsages {
+                path: "#/c".to_owned(),
+            },
+        );
+        let expected = ["#/a"];
+        if state.usages_state.results != expected {
+            bail!(
+                "Expected {:?}, got {:?}",
+                expected,
+                state.usages_state.results
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_problems() -> anyhow::Result<()> {
+        let doc: Value = serde_yaml::from_str("a: {$ref: '#/b'}\nb: 1\n")?;
+        let mut state = state(doc);
+        if !state.problems.is_empty() {
+            bail!("Expected no problems, got {:?}", state.problems);
+        }
+
+        // Pointing `a` at nothing is noticed, undoing fixes it again
+        let value: Value = serde_yaml::from_str("{$ref: '#/c'}")?;
+        state = reducer(state, Action::DocumentReplaceCurrent { value });
+        let paths = state
+            .problems
+            .iter()
+            .map(|problem| problem.path.as_str())
+            .collect::<Vec<_>>();
+        if paths != ["#/a"] {
+            bail!("Expected a problem at #/a, got {:?}", state.problems);
+        }
+
+        // Refused changes don't check the document again
+        state.problems.clear();
+        state.read_only = true;
+        state = reducer(state, Action::Undo);
+        if !state.problems.is_empty() {
+            bail!("Expected no check, got {:?}", state.problems);
+        }
+
+        state.read_only = false;
+        state = reducer(state, Action::Undo);
+        if !state.problems.is_empty() {
+            bail!("Expected no problems, got {:?}", state.problems);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+//! `$ref`s into other files, like `./schemas/user.yaml#/User` or `common.json#/defs/Id`.
+
+use std::{env, fs, path::Path};
+
+use anyhow::{bail, Context as _};
+
+use crate::{state::ROOT_PATH, util::STDIN};
+
+/// Splits `reference` into the file it points into, empty for the same document, and the
+/// pointer within it.
+pub fn split(reference: &str) -> (&str, String) {
+    let (file, fragment) = reference.split_once('#').unwrap_or((reference, ""));
+    (file, format!("{ROOT_PATH}{fragment}"))
+}
+
+/// Absolute path of `file`, relative to the directory of `base` unless it is absolute itself.
+pub fn resolve(base: &str, file: &str) -> anyhow::Result<String> {
+    if file.contains("://") {
+        bail!("Only references to local files can be followed, not {file}");
+    }
+
+    let dir = if base == STDIN {
+        env::current_dir()?
+    } else {
+        Path::new(base)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    };
+    let path = dir.join(file);
+    let path =
+        fs::canonicalize(&path).with_context(|| format!("Unable to find {}", path.display()))?;
+
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Whether `file` is the document being edited.
+pub fn is_edited(file: &str, file_name: &str) -> bool {
+    file_name != STDIN
+        && fs::canonicalize(file_name).is_ok_and(|path| path.to_string_lossy() == file)
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::bail;
+
+    use super::*;
+
+    #[test]
+    fn test_split() -> anyhow::Result<()> {
+        let cases = [
+            (
+                "./schemas/user.yaml#/User",
+                ("./schemas/user.yaml", "#/User"),
+            ),
+            ("common.json", ("common.json", "#")),
+            ("#/components/schemas/Pet", ("", "#/components/schemas/Pet")),
+        ];
+
+        for (reference, (file, pointer)) in cases {
+            let split = split(reference);
+            if split != (file, pointer.to_owned()) {
+                bail!(
+                    "Expected {:?} for {:?}, got {:?}",
+                    (file, pointer),
+                    reference,
+                    split
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve() -> anyhow::Result<()> {
+        let dir = env::temp_dir().join(format!("rover-reference-{}", std::process::id()));
+        fs::create_dir_all(dir.join("schemas"))?;
+        fs::write(dir.join("spec.yaml"), "a: 1\n")?;
+        fs::write(dir.join("schemas").join("user.yaml"), "User: {}\n")?;
+
+        let base = dir.join("spec.yaml").to_string_lossy().into_owned();
+        let user = resolve(&base, "./schemas/user.yaml")?;
+        let expected = fs::canonicalize(dir.join("schemas").join("user.yaml"))?;
+        if Path::new(&user) != expected {
+            bail!("Expected {:?}, got {:?}", expected, user);
+        }
+
+        let spec = resolve(&user, "../spec.yaml")?;
+        if !is_edited(&spec, &base) {
+            bail!("Expected {:?} to be the edited document {:?}", spec, base);
+        }
+
+        for file in ["missing.yaml", "https://example.com/spec.yaml"] {
+            if let Ok(path) = resolve(&base, file) {
+                bail!("Expected {:?} to be refused, got {:?}", file, path);
+            }
+        }
+
+        fs::remove_dir_all(dir)?;
+
+        Ok(())
+    }
+}
@@ -8,6 +8,8 @@ use std::sync::Mutex;
 
 use lazy_static::lazy_static;
 
+use crate::config;
+
 use super::Algorithm;
 
 lazy_static! {
@@ -26,16 +28,23 @@ pub struct Fuzzy {
     target_gap_extend: i16,
 }
 
-impl Default for Fuzzy {
-    fn default() -> Self {
+impl Fuzzy {
+    ///
+    pub const fn new(weights: config::Fuzzy) -> Self {
         Self {
-            eq: 16,
-            target_gap: -3,
-            target_gap_extend: -1,
+            eq: weights.eq,
+            target_gap: weights.target_gap,
+            target_gap_extend: weights.target_gap_extend,
         }
     }
 }
 
+impl Default for Fuzzy {
+    fn default() -> Self {
+        Self::new(config::Fuzzy::default())
+    }
+}
+
 /// Makes sure that all characters in query
 /// exist in the query string in the correct order.
 fn validate(target: &[char], query: &[char]) -> bool {
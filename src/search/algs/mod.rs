@@ -97,7 +97,7 @@ pub fn score(
             let result: HashMap<String, i16> = existing
                 .keys()
                 .filter_map(|key| {
-                    let fuzzy = fuzzy::Fuzzy::default();
+                    let fuzzy = fuzzy::Fuzzy::new(cache.weights);
                     let score = fuzzy.score(key, pattern);
                     (score > 0).then_some((key.clone(), score))
                 })
@@ -287,7 +287,7 @@ fn score_deref(
             let result: HashMap<String, i16> = existing
                 .keys()
                 .filter_map(|key| {
-                    let fuzzy = fuzzy::Fuzzy::default();
+                    let fuzzy = fuzzy::Fuzzy::new(deref_cache.weights);
 
                     key.parse::<ValuePointer>()
                         .ok()
@@ -314,7 +314,7 @@ fn score_deref(
 
                                 Some(score)
                             }
-                            &Value::Null => None,
+                            &Value::Null | &Value::Bytes(_) => None,
                         })
                         .and_then(|score| (score > 0).then_some((key.clone(), score)))
                 })
@@ -392,7 +392,7 @@ fn score_deref(
 
                                 Some(score)
                             }
-                            &Value::Null => None,
+                            &Value::Null | &Value::Bytes(_) => None,
                         })
                         .and_then(|score| (score > 0).then_some((key.clone(), score)))
                 })
@@ -470,7 +470,7 @@ fn score_deref(
 
                                 Some(score)
                             }
-                            &Value::Null => None,
+                            &Value::Null | &Value::Bytes(_) => None,
                         })
                         .and_then(|score| (score > 0).then_some((key.clone(), score)))
                 })
@@ -548,7 +548,7 @@ fn score_deref(
 
                                 Some(score)
                             }
-                            &Value::Null => None,
+                            &Value::Null | &Value::Bytes(_) => None,
                         })
                         .and_then(|score| (score > 0).then_some((key.clone(), score)))
                 })
@@ -26,8 +26,11 @@ impl<T> SimpleStore<T> {
         self.cache.get(key)
     }
 
-    ///
+    /// Stores `value`, evicting the oldest entry once `capacity` is reached.
     pub fn set(&mut self, key: &str, value: T) {
+        if !self.cache.contains_key(key) && self.cache.len() >= self.capacity {
+            self.cache.shift_remove_index(0);
+        }
         self.cache.insert(key.to_owned(), value);
     }
 
@@ -67,6 +70,18 @@ mod test {
         assert_eq!(1, sub_view.len());
     }
 
+    #[test]
+    fn test_capacity() {
+        let mut cache: SimpleStore<u8> = SimpleStore::new(2);
+        cache.set("a", 1);
+        cache.set("b", 2);
+        cache.set("c", 3);
+
+        assert!(cache.get("a").is_none());
+        assert_eq!(Some(&2), cache.get("b"));
+        assert_eq!(Some(&3), cache.get("c"));
+    }
+
     #[test]
     fn test_merge() {
         let root = HashSet::from([String::from("a"), String::from("b"), String::from("c")]);
@@ -2,7 +2,7 @@
 
 use std::collections::HashMap;
 
-use crate::value::Value;
+use crate::{config, pointer::Walk, state::ROOT_PATH, value::Value};
 
 use self::cache::SimpleStore;
 
@@ -23,6 +23,8 @@ pub struct PatternCache {
     pub suffix: SimpleStore<HashMap<String, i16>>,
     ///
     pub fuzzy: SimpleStore<HashMap<String, i16>>,
+    /// Weights fuzzy patterns are scored with.
+    pub weights: config::Fuzzy,
 }
 
 impl PatternCache {
@@ -34,9 +36,16 @@ impl PatternCache {
             prefix: SimpleStore::new(capacity),
             suffix: SimpleStore::new(capacity),
             fuzzy: SimpleStore::new(capacity),
+            weights: config::Fuzzy::default(),
         }
     }
 
+    /// Scores fuzzy patterns with `weights` instead of the defaults.
+    pub const fn weights(mut self, weights: config::Fuzzy) -> Self {
+        self.weights = weights;
+        self
+    }
+
     ///
     pub fn reset(&mut self, core: HashMap<String, i16>) {
         self.core = core;
@@ -48,6 +57,61 @@ impl PatternCache {
     }
 }
 
+/// Every path in `doc`, the set searches are run against.
+pub fn index(doc: &Value) -> HashMap<String, i16> {
+    Walk::new(doc)
+        .skip_refs()
+        .map(|(path, _, _)| (path, 0))
+        .collect()
+}
+
+/// Every `$ref` in `doc` pointing into `doc` itself, as the path of the map holding it
+/// and its target, in document order.
+pub fn references(doc: &Value) -> Vec<(String, String)> {
+    Walk::new(doc)
+        .filter_map(|(path, value, _)| {
+            if let &Value::Object(ref map) = value {
+                if let Some(&Value::String(ref target)) = map.get("$ref") {
+                    return target
+                        .starts_with(ROOT_PATH)
+                        .then(|| (path, target.clone()));
+                }
+            }
+
+            None
+        })
+        .collect()
+}
+
+/// Paths of the `$ref`s in `references` targeting `path` or anything below it.
+pub fn usages(references: &[(String, String)], path: &str) -> Vec<String> {
+    let below = format!("{path}/");
+    references
+        .iter()
+        .filter(|&&(_, ref target)| target == path || target.starts_with(&below))
+        .map(|&(ref usage, _)| usage.clone())
+        .collect()
+}
+
+/// Paths matching `query` with their scores, best match first.
+pub fn ranked(
+    doc: &Value,
+    cache: &mut PatternCache,
+    deref_cache: &mut PatternCache,
+    query: &str,
+) -> anyhow::Result<Vec<(String, i16)>> {
+    let patterns = query.parse::<parser::Pattern>()?;
+
+    let mut result = algs::score(&patterns, cache, deref_cache, doc)
+        .into_iter()
+        .collect::<Vec<_>>();
+    result.sort_by(|&(ref a_key, ref a), &(ref b_key, ref b)| {
+        b.cmp(a).then_with(|| a_key.cmp(b_key))
+    });
+
+    Ok(result)
+}
+
 ///
 pub fn search(
     doc: &Value,
@@ -55,15 +119,102 @@ pub fn search(
     deref_cache: &mut PatternCache,
     query: &str,
 ) -> Vec<String> {
-    let patterns = query.parse::<parser::Pattern>();
+    ranked(doc, cache, deref_cache, query)
+        .map(|result| result.into_iter().map(|(key, _)| key).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::bail;
 
-    if let Ok(patterns) = patterns {
-        let mut result = algs::score(&patterns, cache, deref_cache, doc)
+    use super::*;
+
+    #[test]
+    fn test_index_escapes_keys() -> anyhow::Result<()> {
+        let doc: Value = serde_yaml::from_str("paths:\n  /pets~v2: [1]\n")?;
+
+        let mut paths = index(&doc).into_keys().collect::<Vec<_>>();
+        paths.sort();
+
+        let expected = ["#", "#/paths", "#/paths/~1pets~0v2", "#/paths/~1pets~0v2/0"];
+        if paths != expected {
+            bail!("Expected {:?}, got {:?}", expected, paths);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ranked() -> anyhow::Result<()> {
+        let doc: Value = serde_yaml::from_str("b:\n  operationId: x\na:\n  operationId: y\n")?;
+
+        let mut cache = PatternCache::new(10);
+        let mut deref_cache = PatternCache::new(10);
+        cache.reset(index(&doc));
+        deref_cache.reset(index(&doc));
+
+        let results = ranked(&doc, &mut cache, &mut deref_cache, "operationId$")?;
+        let paths = results
             .into_iter()
+            .map(|(path, _)| path)
             .collect::<Vec<_>>();
-        result.sort_by(|&(_, ref a), &(_, ref b)| b.cmp(a));
-        result.into_iter().map(|(key, _)| key).collect()
-    } else {
-        Vec::new()
+
+        // Equal scores are ordered by path
+        let expected = ["#/a/operationId", "#/b/operationId"];
+        if paths != expected {
+            bail!("Expected {:?}, got {:?}", expected, paths);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_usages() -> anyhow::Result<()> {
+        let doc: Value = serde_yaml::from_str(
+            "
+paths:
+  /pets:
+    get: {$ref: '#/components/responses/Pets'}
+    post: {$ref: '#/components/schemas/Pet'}
+  /owners:
+    get: {$ref: '#/components/schemas/PetOwner'}
+    put: {$ref: 'other.yaml#/components/schemas/Pet'}
+components:
+  schemas:
+    Pet:
+      properties:
+        tag: {$ref: '#/components/schemas/Pet/properties/name'}
+",
+        )?;
+        let references = references(&doc);
+
+        let cases = [
+            (
+                "#/components/schemas/Pet",
+                vec![
+                    "#/paths/~1pets/post",
+                    "#/components/schemas/Pet/properties/tag",
+                ],
+            ),
+            (
+                "#/components/schemas",
+                vec![
+                    "#/paths/~1pets/post",
+                    "#/paths/~1owners/get",
+                    "#/components/schemas/Pet/properties/tag",
+                ],
+            ),
+            ("#/components/responses/Pets/description", vec![]),
+        ];
+
+        for (path, expected) in cases {
+            let found = usages(&references, path);
+            if found != expected {
+                bail!("Expected {:?} for {:?}, got {:?}", expected, path, found);
+            }
+        }
+
+        Ok(())
     }
 }
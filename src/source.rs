@@ -0,0 +1,1357 @@
+//! Source text of a loaded document along with where every node lives in it,
+//! so saving only rewrites the nodes that were edited and leaves comments,
+//! quoting and formatting everywhere else untouched.
+
+use std::collections::HashMap;
+
+use saphyr_parser::{Event, Parser, ScalarStyle};
+
+use crate::{
+    format::{blank_comments, Format},
+    pointer::escape,
+    state::ROOT_PATH,
+    value::Value,
+};
+
+///
+#[derive(Clone, Debug)]
+struct Span {
+    /// Start of the text that belongs to the node's parent entry (the key of
+    /// a mapping entry), used when entries get reordered.
+    entry: usize,
+
+    /// Start of the text that gets replaced when the node is rewritten. In
+    /// block context this is right after the `:` or `-` indicator.
+    slot: usize,
+
+    ///
+    end: usize,
+
+    /// Column of the key or `-` indicator that introduces the node.
+    indent: usize,
+
+    /// Whether the node sits in a block collection (as opposed to a flow one).
+    block: bool,
+
+    /// Whether the node itself is a sequence item (as opposed to a mapping value).
+    item: bool,
+}
+
+///
+enum Frame {
+    ///
+    Map {
+        ///
+        path: String,
+
+        ///
+        start: usize,
+
+        ///
+        flow: bool,
+
+        ///
+        key: Option<(String, usize, usize)>,
+
+        ///
+        last_end: usize,
+    },
+
+    ///
+    Seq {
+        ///
+        path: String,
+
+        ///
+        start: usize,
+
+        ///
+        flow: bool,
+
+        ///
+        index: usize,
+
+        ///
+        last_end: usize,
+    },
+}
+
+///
+#[derive(Clone, Debug)]
+pub struct Source {
+    ///
+    format: Format,
+
+    ///
+    text: String,
+
+    /// The document as it was parsed from `text`.
+    original: Value,
+
+    ///
+    spans: HashMap<String, Span>,
+
+    /// For a YAML stream, every document along with the text leading up to it
+    /// (its `---` marker).
+    documents: Vec<(String, Source)>,
+}
+
+impl Source {
+    /// Indexes `text`, returns `None` if the layout can't be tracked (e.g. complex keys
+    /// or multiple documents), in which case saving falls back to a full rewrite.
+    pub fn new(format: Format, text: String, original: Value) -> Option<Self> {
+        if format == Format::YamlStream {
+            let documents = split_documents(&text, &original)?;
+
+            return Some(Self {
+                format,
+                text,
+                original,
+                spans: HashMap::new(),
+                documents,
+            });
+        }
+
+        // Only formats the YAML parser understands can be indexed, JSON being a subset.
+        // JSON5 comments are blanked out first, which keeps every position intact
+        let spans = match format {
+            Format::Json | Format::Yaml => index(&text)?,
+            Format::Json5 => index(&blank_comments(&text))?,
+            Format::YamlStream
+            | Format::Toml
+            | Format::JsonLines
+            | Format::MessagePack
+            | Format::Cbor => return None,
+        };
+
+        Some(Self {
+            format,
+            text,
+            original,
+            spans,
+            documents: Vec::new(),
+        })
+    }
+
+    ///
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Whether the original text has comments, see [`Format::has_comments`].
+    pub fn has_comments(&self) -> bool {
+        self.format.has_comments(&self.text)
+    }
+
+    /// Renders `doc` by patching the original text, `None` if that isn't possible.
+    pub fn render(&self, doc: &Value) -> Option<String> {
+        if identical(doc, &self.original) {
+            return Some(self.text.clone());
+        }
+
+        let out = if self.format == Format::YamlStream {
+            self.render_stream(doc)?
+        } else {
+            let root = self.spans.get(ROOT_PATH)?;
+            let mut out = String::with_capacity(self.text.len());
+
+            out.push_str(self.text.get(..root.slot)?);
+            self.render_node(ROOT_PATH, &self.original, doc, &mut out)?;
+            out.push_str(self.text.get(root.end..)?);
+            out
+        };
+
+        // Only trust the patched text if it reads back as exactly the same document
+        let reparsed = self.format.parse(&out).ok()?;
+
+        identical(&reparsed, doc).then_some(out)
+    }
+
+    /// Renders every document with the layout of the one it came from, new
+    /// documents are appended after a fresh `---` marker.
+    fn render_stream(&self, doc: &Value) -> Option<String> {
+        let (&Value::Array(ref old_docs), &Value::Array(ref new_docs)) = (&self.original, doc)
+        else {
+            return None;
+        };
+
+        let sources = if old_docs.len() == new_docs.len() {
+            reordered(old_docs, new_docs)
+        } else {
+            (0..new_docs.len()).collect()
+        };
+
+        let mut out = String::with_capacity(self.text.len());
+        for (idx, (source_idx, new_doc)) in sources.into_iter().zip(new_docs).enumerate() {
+            let lead = self.documents.get(idx).map(|&(ref lead, _)| lead);
+            let source = self
+                .documents
+                .get(source_idx)
+                .map(|&(_, ref source)| source);
+
+            if let (Some(lead), Some(source)) = (lead, source) {
+                out.push_str(lead);
+                out.push_str(&source.render(new_doc)?);
+            } else {
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str("---\n");
+                out.push_str(&serde_yaml::to_string(new_doc).ok()?);
+            }
+        }
+
+        Some(out)
+    }
+
+    ///
+    fn render_node(&self, path: &str, old: &Value, new: &Value, out: &mut String) -> Option<()> {
+        let span = self.spans.get(path)?;
+
+        if identical(old, new) {
+            out.push_str(self.text.get(span.slot..span.end)?);
+            return Some(());
+        }
+
+        match (old, new) {
+            (&Value::Object(ref old_map), &Value::Object(ref new_map))
+                if !old_map.is_empty()
+                    && old_map.len() == new_map.len()
+                    && new_map.keys().all(|key| old_map.contains_key(key))
+                    && old_map
+                        .keys()
+                        .all(|key| self.spans.contains_key(&child(path, key))) =>
+            {
+                let mut cursor = span.slot;
+
+                for (old_key, (new_key, new_value)) in old_map.keys().zip(new_map) {
+                    let slot = self.spans.get(&child(path, &old_key))?;
+                    let new_path = child(path, &new_key);
+                    let moved = self.spans.get(&new_path)?;
+
+                    out.push_str(self.text.get(cursor..slot.entry)?);
+                    out.push_str(self.text.get(moved.entry..moved.slot)?);
+                    self.render_node(&new_path, old_map.get(new_key)?, new_value, out)?;
+                    // A comment after the entry moves with it
+                    out.push_str(self.text.get(moved.end..self.entry_end(moved)?)?);
+
+                    cursor = self.entry_end(slot)?;
+                }
+
+                out.push_str(self.text.get(cursor..span.end)?);
+                Some(())
+            }
+            (&Value::Array(ref old_arr), &Value::Array(ref new_arr))
+                if !old_arr.is_empty()
+                    && old_arr.len() == new_arr.len()
+                    && (0..old_arr.len())
+                        .all(|idx| self.spans.contains_key(&child(path, &idx))) =>
+            {
+                let mut cursor = span.slot;
+
+                for (idx, (source_idx, new_value)) in reordered(old_arr, new_arr)
+                    .into_iter()
+                    .zip(new_arr)
+                    .enumerate()
+                {
+                    let slot = self.spans.get(&child(path, &idx))?;
+                    let source_path = child(path, &source_idx);
+                    let moved = self.spans.get(&source_path)?;
+
+                    out.push_str(self.text.get(cursor..slot.entry)?);
+                    out.push_str(self.text.get(moved.entry..moved.slot)?);
+                    self.render_node(&source_path, old_arr.get(source_idx)?, new_value, out)?;
+                    out.push_str(self.text.get(moved.end..self.entry_end(moved)?)?);
+
+                    cursor = self.entry_end(slot)?;
+                }
+
+                out.push_str(self.text.get(cursor..span.end)?);
+                Some(())
+            }
+            (&Value::Object(_), &Value::Object(_)) | (&Value::Array(_), &Value::Array(_)) => {
+                let mut entries = String::new();
+                if self
+                    .render_entries(path, span, old, new, &mut entries)
+                    .is_some()
+                {
+                    out.push_str(&entries);
+                    Some(())
+                } else {
+                    self.render_fresh(path, span, new, out)
+                }
+            }
+            (&Value::String(_), &Value::String(ref new)) if self.format == Format::Yaml => {
+                self.render_quoted(path, span, new, out)
+            }
+            _ => self.render_fresh(path, span, new, out),
+        }
+    }
+
+    /// Renders a YAML string in the quotes the one it replaces had, so `"1"` doesn't turn
+    /// into a plain `2.0.0`.
+    fn render_quoted(&self, path: &str, span: &Span, new: &str, out: &mut String) -> Option<()> {
+        let text = self.text.get(span.slot..span.end)?;
+        let scalar = text.trim_start();
+        let padding = text.get(..text.len().saturating_sub(scalar.len()))?;
+
+        match scalar.chars().next() {
+            // JSON escapes are all valid in double quoted YAML
+            Some('"') => {
+                out.push_str(padding);
+                out.push_str(&serde_json::to_string(new).ok()?);
+            }
+            Some('\'') if !new.contains(char::is_control) => {
+                out.push_str(padding);
+                out.push('\'');
+                out.push_str(&new.replace('\'', "''"));
+                out.push('\'');
+            }
+            Some(_) | None => {
+                return self.render_fresh(path, span, &Value::String(new.to_owned()), out)
+            }
+        }
+
+        Some(())
+    }
+
+    /// Renders a collection that had entries added or removed, keeping the text
+    /// of the entries that are still there. Entries that are kept have to stay
+    /// in their original order, everything after the first one that doesn't is
+    /// written fresh at the end.
+    fn render_entries(
+        &self,
+        path: &str,
+        span: &Span,
+        old: &Value,
+        new: &Value,
+        out: &mut String,
+    ) -> Option<()> {
+        let mut children = Vec::new();
+        let mut kept = Vec::new();
+        let mut added = Vec::new();
+
+        match (old, new) {
+            (&Value::Object(ref old_map), &Value::Object(ref new_map)) => {
+                for (key, old_value) in old_map {
+                    children.push((child(path, key), old_value));
+                }
+
+                for (key, new_value) in new_map {
+                    let idx = old_map.get_index_of(key);
+                    match idx {
+                        Some(idx)
+                            if added.is_empty()
+                                && kept.last().is_none_or(|&(last, _)| idx > last) =>
+                        {
+                            kept.push((idx, new_value));
+                        }
+                        Some(_) | None => added.push((Some(key.as_str()), new_value)),
+                    }
+                }
+            }
+            (&Value::Array(ref old_arr), &Value::Array(ref new_arr)) => {
+                for (idx, old_value) in old_arr.iter().enumerate() {
+                    children.push((child(path, &idx), old_value));
+                }
+
+                let mut idx = 0;
+                for new_value in new_arr {
+                    if added.is_empty() {
+                        while old_arr
+                            .get(idx)
+                            .is_some_and(|old_value| !identical(old_value, new_value))
+                        {
+                            idx = idx.saturating_add(1);
+                        }
+
+                        if idx < old_arr.len() {
+                            kept.push((idx, new_value));
+                            idx = idx.saturating_add(1);
+                            continue;
+                        }
+                    }
+
+                    added.push((None, new_value));
+                }
+            }
+            _ => return None,
+        }
+
+        if kept.is_empty() {
+            return None;
+        }
+
+        let spans = children
+            .iter()
+            .map(|&(ref child_path, _)| self.spans.get(child_path))
+            .collect::<Option<Vec<_>>>()?;
+        let first = spans.first()?;
+        let last = spans.last()?;
+
+        if first.block {
+            self.render_block_entries(span, &children, &spans, &kept, &added, out)?;
+        } else {
+            self.render_flow_entries(span, &children, &spans, &kept, &added, out)?;
+        }
+
+        out.push_str(self.text.get(self.entry_end(last)?..span.end)?);
+        Some(())
+    }
+
+    /// Block entries are whole lines, each kept one brings along the comment
+    /// lines above it.
+    fn render_block_entries(
+        &self,
+        span: &Span,
+        children: &[(String, &Value)],
+        spans: &[&Span],
+        kept: &[(usize, &Value)],
+        added: &[(Option<&str>, &Value)],
+        out: &mut String,
+    ) -> Option<()> {
+        let first = spans.first()?;
+        let line_start = self.line_start(first.entry);
+        if line_start < span.slot {
+            // Shares its line with the parent entry, like a mapping in a sequence item
+            return None;
+        }
+        out.push_str(self.text.get(span.slot..line_start)?);
+
+        for (position, &(idx, new_value)) in kept.iter().enumerate() {
+            let &(ref child_path, old_value) = children.get(idx)?;
+            let child = spans.get(idx)?;
+
+            let lead = match idx.checked_sub(1).and_then(|prev| spans.get(prev)) {
+                Some(prev) => self
+                    .text
+                    .get(self.entry_end(prev)?..self.line_start(child.entry))?,
+                None => "",
+            };
+            if position == 0 {
+                out.push_str(lead.strip_prefix('\n').unwrap_or(lead));
+            } else {
+                out.push_str(lead);
+            }
+
+            out.push_str(self.text.get(self.line_start(child.entry)..child.slot)?);
+            self.render_node(child_path, old_value, new_value, out)?;
+            out.push_str(self.text.get(child.end..self.entry_end(child)?)?);
+        }
+
+        for &(key, new_value) in added {
+            out.push('\n');
+            out.push_str(&" ".repeat(first.indent));
+            self.render_entry(key, first, new_value, out)?;
+        }
+
+        Some(())
+    }
+
+    /// Flow entries keep the separator that came before them, added ones reuse
+    /// the last separator of the collection.
+    fn render_flow_entries(
+        &self,
+        span: &Span,
+        children: &[(String, &Value)],
+        spans: &[&Span],
+        kept: &[(usize, &Value)],
+        added: &[(Option<&str>, &Value)],
+        out: &mut String,
+    ) -> Option<()> {
+        let first = spans.first()?;
+        let leading = self.text.get(span.slot..first.entry)?;
+        out.push_str(leading);
+
+        let separator = |idx: usize| -> Option<&str> {
+            let prev = spans.get(idx.checked_sub(1)?)?;
+            self.text.get(prev.end..spans.get(idx)?.entry)
+        };
+        let fallback = if leading.contains('\n') {
+            format!(",\n{}", self.line_indent(first.entry))
+        } else {
+            ", ".to_owned()
+        };
+        let last_separator = separator(spans.len().saturating_sub(1))
+            .map_or_else(|| fallback.clone(), str::to_owned);
+
+        for (position, &(idx, new_value)) in kept.iter().enumerate() {
+            let &(ref child_path, old_value) = children.get(idx)?;
+            let child = spans.get(idx)?;
+
+            if position > 0 {
+                out.push_str(separator(idx).unwrap_or(&fallback));
+            }
+
+            out.push_str(self.text.get(child.entry..child.slot)?);
+            self.render_node(child_path, old_value, new_value, out)?;
+        }
+
+        for &(key, new_value) in added {
+            out.push_str(&last_separator);
+            self.render_entry(key, first, new_value, out)?;
+        }
+
+        Some(())
+    }
+
+    /// Writes a new entry laid out like its sibling `like`, `key` is `None` for
+    /// a sequence item.
+    fn render_entry(
+        &self,
+        key: Option<&str>,
+        like: &Span,
+        value: &Value,
+        out: &mut String,
+    ) -> Option<()> {
+        match key {
+            Some(key) if like.block => {
+                let key = serde_yaml::to_string(key).ok()?;
+                out.push_str(key.trim_end());
+                out.push(':');
+            }
+            Some(key) => {
+                out.push_str(&serde_json::to_string(key).ok()?);
+                out.push_str(": ");
+            }
+            None if like.block => out.push('-'),
+            None => {}
+        }
+
+        // Anything but the root path, the layout comes from `like`
+        self.render_fresh("", like, value, out)
+    }
+
+    /// End of the text that goes with the entry of `span`, including a comment
+    /// that follows it on the same line.
+    fn entry_end(&self, span: &Span) -> Option<usize> {
+        if span.block {
+            trailing_comment_end(&self.text, span.end)
+        } else {
+            Some(span.end)
+        }
+    }
+
+    /// Start of the line containing `offset`.
+    fn line_start(&self, offset: usize) -> usize {
+        self.text
+            .get(..offset)
+            .and_then(|before| before.rfind('\n'))
+            .map_or(0, |idx| idx.saturating_add(1))
+    }
+
+    /// Serializes `new` from scratch, indented to fit where `span` used to be.
+    fn render_fresh(&self, path: &str, span: &Span, new: &Value, out: &mut String) -> Option<()> {
+        let line_indent = self.line_indent(span.slot);
+
+        if path == ROOT_PATH {
+            let rendered = match self.format {
+                Format::Json | Format::Json5 => pretty_json(new, &self.indent_unit())?,
+                Format::Yaml => serde_yaml::to_string(new).ok()?,
+                Format::YamlStream
+                | Format::Toml
+                | Format::JsonLines
+                | Format::MessagePack
+                | Format::Cbor => return None,
+            };
+            out.push_str(rendered.trim_end());
+            return Some(());
+        }
+
+        if !span.block {
+            let rendered = match self.format {
+                Format::Json | Format::Json5 => pretty_json(new, &self.indent_unit())?,
+                Format::Yaml => serde_json::to_string(new).ok()?,
+                Format::YamlStream
+                | Format::Toml
+                | Format::JsonLines
+                | Format::MessagePack
+                | Format::Cbor => return None,
+            };
+            out.push_str(&reindent(&rendered, &line_indent, false));
+            return Some(());
+        }
+
+        let rendered = serde_yaml::to_string(new).ok()?;
+        let rendered = rendered.trim_end();
+
+        let nested = match new {
+            &Value::Object(ref map) => !map.is_empty(),
+            &Value::Array(ref arr) => !arr.is_empty(),
+            &(Value::Null
+            | Value::Bool(_)
+            | Value::Number(_)
+            | Value::String(_)
+            | Value::Bytes(_)) => false,
+        };
+
+        let child_indent = " ".repeat(span.indent.saturating_add(2));
+        if nested && !span.item {
+            out.push('\n');
+            out.push_str(&reindent(rendered, &child_indent, true));
+        } else {
+            out.push(' ');
+            out.push_str(&reindent(rendered, &child_indent, false));
+        }
+
+        Some(())
+    }
+
+    /// Leading whitespace of the line containing `offset`.
+    fn line_indent(&self, offset: usize) -> String {
+        let line_start = self
+            .text
+            .get(..offset)
+            .and_then(|before| before.rfind('\n'))
+            .map_or(0, |idx| idx.saturating_add(1));
+
+        self.text
+            .get(line_start..)
+            .unwrap_or_default()
+            .chars()
+            .take_while(|ch| *ch == ' ' || *ch == '\t')
+            .collect()
+    }
+
+    /// The indentation used by the first indented line in the file.
+    fn indent_unit(&self) -> String {
+        self.text
+            .lines()
+            .map(|line| {
+                line.chars()
+                    .take_while(|ch| *ch == ' ' || *ch == '\t')
+                    .collect::<String>()
+            })
+            .find(|indent| !indent.is_empty())
+            .unwrap_or_else(|| "  ".to_owned())
+    }
+}
+
+/// Like `==` but also compares the order of object keys.
+fn identical(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (&Value::Object(ref left), &Value::Object(ref right)) => {
+            left.len() == right.len()
+                && left
+                    .iter()
+                    .zip(right)
+                    .all(|((lk, lv), (rk, rv))| lk == rk && identical(lv, rv))
+        }
+        (&Value::Array(ref left), &Value::Array(ref right)) => {
+            left.len() == right.len() && left.iter().zip(right).all(|(l, r)| identical(l, r))
+        }
+        _ => left == right,
+    }
+}
+
+///
+fn child<K: ToString + ?Sized>(path: &str, key: &K) -> String {
+    format!("{path}/{}", escape(&key.to_string()))
+}
+
+/// For every item in `new` picks the item of `old` whose text should be reused,
+/// preferring unchanged items (so swaps move text around) and falling back to
+/// the item that was at the same position.
+fn reordered(old: &[Value], new: &[Value]) -> Vec<usize> {
+    let mut used = vec![false; old.len()];
+    let mut sources: Vec<Option<usize>> = new
+        .iter()
+        .map(|value| {
+            let found = old.iter().enumerate().position(|(idx, candidate)| {
+                identical(candidate, value) && used.get(idx).is_some_and(|used| !used)
+            });
+
+            if let Some(idx) = found {
+                if let Some(used) = used.get_mut(idx) {
+                    *used = true;
+                }
+            }
+
+            found
+        })
+        .collect();
+
+    for (idx, source) in sources.iter_mut().enumerate() {
+        if source.is_none() && used.get(idx).is_some_and(|used| !used) {
+            if let Some(used) = used.get_mut(idx) {
+                *used = true;
+            }
+            *source = Some(idx);
+        }
+    }
+
+    let mut unused = used
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, used)| (!used).then_some(idx));
+
+    sources
+        .into_iter()
+        .map(|source| source.or_else(|| unused.next()).unwrap_or_default())
+        .collect()
+}
+
+///
+fn pretty_json(value: &Value, indent: &str) -> Option<String> {
+    use serde::Serialize as _;
+
+    let mut buffer = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, formatter);
+    value.serialize(&mut serializer).ok()?;
+
+    String::from_utf8(buffer).ok()
+}
+
+/// Prefixes lines with `indent`, the first line only when `first` is set.
+fn reindent(text: &str, indent: &str, first: bool) -> String {
+    text.split('\n')
+        .enumerate()
+        .map(|(idx, line)| {
+            if (idx > 0 || first) && !line.is_empty() {
+                format!("{indent}{line}")
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Splits a YAML stream on its `---` markers into the text leading up to each
+/// document and a [`Source`] for the document itself.
+fn split_documents(text: &str, original: &Value) -> Option<Vec<(String, Source)>> {
+    let &Value::Array(ref docs) = original else {
+        return None;
+    };
+
+    let mut markers = Vec::new();
+    let mut offset: usize = 0;
+    for line in text.split_inclusive('\n') {
+        if line
+            .strip_prefix("---")
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        {
+            markers.push(offset);
+        }
+        offset = offset.saturating_add(line.len());
+    }
+
+    // Either every document has a marker, or the first one goes without
+    let mut bounds: Vec<(usize, usize)> = Vec::with_capacity(docs.len());
+    if markers.len().saturating_add(1) == docs.len() {
+        bounds.push((0, 0));
+    }
+    if bounds.len().saturating_add(markers.len()) != docs.len() {
+        return None;
+    }
+    for &marker in &markers {
+        bounds.push((marker, marker.saturating_add(3)));
+    }
+
+    let mut documents = Vec::with_capacity(docs.len());
+    for (idx, (&(lead_end, start), doc)) in bounds.iter().zip(docs).enumerate() {
+        // Comments before the first marker are kept along with it
+        let lead_start = if idx == 0 { 0 } else { lead_end };
+        let end = bounds
+            .get(idx.saturating_add(1))
+            .map_or(text.len(), |&(next, _)| next);
+
+        let lead = text.get(lead_start..start)?.to_owned();
+        let source = Source::new(Format::Yaml, text.get(start..end)?.to_owned(), doc.clone())?;
+        documents.push((lead, source));
+    }
+
+    Some(documents)
+}
+
+/// Builds the span of every node in `text`, keyed by its JSON pointer.
+fn index(text: &str) -> Option<HashMap<String, Span>> {
+    let offsets = char_offsets(text);
+    let byte = |idx: usize| byte_offset(offsets.as_deref(), idx);
+
+    let mut spans = HashMap::new();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut documents: usize = 0;
+
+    for event in Parser::new_from_str(text) {
+        let (event, marker) = event.ok()?;
+        let start = byte(marker.start.index())?;
+        let end = byte(marker.end.index())?;
+
+        match event {
+            Event::DocumentStart(_) => {
+                documents = documents.saturating_add(1);
+                if documents > 1 {
+                    return None;
+                }
+            }
+            Event::Scalar(value, style, _, _) => {
+                if let Some(&mut Frame::Map { ref mut key, .. }) = stack.last_mut() {
+                    if key.is_none() {
+                        *key = Some((value.into_owned(), start, end));
+                        continue;
+                    }
+                }
+
+                let end = match style {
+                    ScalarStyle::Literal | ScalarStyle::Folded => {
+                        start.saturating_add(text.get(start..end)?.trim_end().len())
+                    }
+                    // The parser can stretch quoted spans over trailing comments
+                    ScalarStyle::SingleQuoted | ScalarStyle::DoubleQuoted => {
+                        quoted_end(text, start).unwrap_or(end)
+                    }
+                    ScalarStyle::Plain => end,
+                };
+
+                let (path, span) = place(text, &mut stack, start, end)?;
+                spans.insert(path, span);
+            }
+            Event::Alias(_) => {
+                if let Some(&Frame::Map { key: None, .. }) = stack.last() {
+                    return None;
+                }
+
+                let (path, span) = place(text, &mut stack, start, end)?;
+                spans.insert(path, span);
+            }
+            Event::MappingStart(..) | Event::SequenceStart(..) => {
+                if let Some(&Frame::Map { key: None, .. }) = stack.last() {
+                    // Complex keys aren't supported
+                    return None;
+                }
+
+                let is_map = matches!(event, Event::MappingStart(..));
+                let frame = open(text, &stack, start, is_map)?;
+                stack.push(frame);
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                let close_start = start;
+                let (start, flow, last_end) = match stack.pop()? {
+                    Frame::Map {
+                        start,
+                        flow,
+                        last_end,
+                        ..
+                    }
+                    | Frame::Seq {
+                        start,
+                        flow,
+                        last_end,
+                        ..
+                    } => (start, flow, last_end),
+                };
+
+                let end = if flow {
+                    // With a trailing comma the event points at the comma instead of the bracket
+                    let close = if let Event::MappingEnd = event {
+                        '}'
+                    } else {
+                        ']'
+                    };
+                    let offset = text.get(close_start..)?.find(close)?;
+                    close_start.saturating_add(offset).saturating_add(1)
+                } else {
+                    // A comment after the last entry goes with the collection
+                    trailing_comment_end(text, last_end).unwrap_or(last_end)
+                };
+
+                let (path, span) = place(text, &mut stack, start, end)?;
+                spans.insert(path, span);
+            }
+            Event::Nothing | Event::StreamStart | Event::StreamEnd | Event::DocumentEnd => {}
+        }
+    }
+
+    Some(spans)
+}
+
+/// Starts tracking a mapping or sequence found at `start`.
+fn open(text: &str, stack: &[Frame], start: usize, is_map: bool) -> Option<Frame> {
+    let path = match stack.last() {
+        Some(&Frame::Map {
+            ref path,
+            key: Some((ref key, _, _)),
+            ..
+        }) => child(path, key),
+        Some(&Frame::Seq {
+            ref path, index, ..
+        }) => child(path, &index),
+        Some(&Frame::Map { key: None, .. }) | None => ROOT_PATH.to_owned(),
+    };
+
+    let flow = matches!(text.get(start..)?.chars().next(), Some('{' | '['));
+    Some(if is_map {
+        Frame::Map {
+            path,
+            start,
+            flow,
+            key: None,
+            last_end: start,
+        }
+    } else {
+        Frame::Seq {
+            path,
+            start,
+            flow,
+            index: 0,
+            last_end: start,
+        }
+    })
+}
+
+/// Attaches a node spanning `start..end` to its parent on the stack.
+fn place(text: &str, stack: &mut [Frame], start: usize, end: usize) -> Option<(String, Span)> {
+    match stack.last_mut() {
+        Some(&mut Frame::Map {
+            ref path,
+            flow,
+            ref mut key,
+            ref mut last_end,
+            ..
+        }) => {
+            let (key, key_start, key_end) = key.take()?;
+
+            let slot = if flow {
+                start
+            } else {
+                let colon = text.get(key_end..start)?.find(':')?;
+                key_end.saturating_add(colon).saturating_add(1)
+            };
+
+            *last_end = end;
+            Some((
+                child(path, &key),
+                Span {
+                    entry: key_start,
+                    slot,
+                    end,
+                    indent: column(text, key_start),
+                    block: !flow,
+                    item: false,
+                },
+            ))
+        }
+        Some(&mut Frame::Seq {
+            ref path,
+            flow,
+            ref mut index,
+            ref mut last_end,
+            ..
+        }) => {
+            let (slot, indent) = if flow {
+                (start, column(text, start))
+            } else {
+                let dash = text
+                    .get(..start)?
+                    .char_indices()
+                    .rev()
+                    .find(|&(offset, ch)| {
+                        ch == '-'
+                            && text
+                                .get(offset.saturating_add(1)..)
+                                .and_then(|rest| rest.chars().next())
+                                .is_some_and(char::is_whitespace)
+                    })
+                    .map(|(offset, _)| offset)?;
+
+                (dash.saturating_add(1), column(text, dash))
+            };
+
+            let path = child(path, &index);
+            *index = index.saturating_add(1);
+            *last_end = end;
+
+            Some((
+                path,
+                Span {
+                    entry: slot,
+                    slot,
+                    end,
+                    indent,
+                    block: !flow,
+                    item: true,
+                },
+            ))
+        }
+        None => Some((
+            ROOT_PATH.to_owned(),
+            Span {
+                entry: start,
+                slot: start,
+                end,
+                indent: 0,
+                block: true,
+                item: false,
+            },
+        )),
+    }
+}
+
+/// End of the comment on the rest of the line after `offset`, `offset` itself
+/// if there is only whitespace and `None` if there is anything else.
+fn trailing_comment_end(text: &str, offset: usize) -> Option<usize> {
+    let rest = text.get(offset..)?.split('\n').next()?.trim_end();
+
+    if rest.trim_start().is_empty() {
+        Some(offset)
+    } else if rest.trim_start().starts_with('#') {
+        Some(offset.saturating_add(rest.len()))
+    } else {
+        None
+    }
+}
+
+/// Whether YAML `text` has a comment anywhere, a `#` that isn't part of a scalar.
+pub fn yaml_has_comments(text: &str) -> bool {
+    let offsets = char_offsets(text);
+    let byte = |idx: usize| byte_offset(offsets.as_deref(), idx);
+
+    let mut scalars = Vec::new();
+    for event in Parser::new_from_str(text) {
+        // Without the layout there's no telling, assume the worst
+        let Ok((event, marker)) = event else {
+            return text.contains('#');
+        };
+        let (Some(start), Some(end)) = (byte(marker.start.index()), byte(marker.end.index()))
+        else {
+            return text.contains('#');
+        };
+
+        let end = match event {
+            Event::Scalar(_, ScalarStyle::SingleQuoted | ScalarStyle::DoubleQuoted, _, _) => {
+                quoted_end(text, start).unwrap_or(end)
+            }
+            Event::Scalar(..) | Event::Alias(_) => end,
+            Event::Nothing
+            | Event::StreamStart
+            | Event::StreamEnd
+            | Event::DocumentStart(_)
+            | Event::DocumentEnd
+            | Event::SequenceStart(..)
+            | Event::SequenceEnd
+            | Event::MappingStart(..)
+            | Event::MappingEnd => continue,
+        };
+        scalars.push(start..end);
+    }
+
+    text.char_indices().any(|(offset, ch)| {
+        ch == '#'
+            && text
+                .get(..offset)
+                .is_some_and(|before| before.chars().last().is_none_or(char::is_whitespace))
+            && !scalars.iter().any(|scalar| scalar.contains(&offset))
+    })
+}
+
+/// Byte offset of every char in `text` and of its end, `None` when they are the same
+/// as the char indices.
+fn char_offsets(text: &str) -> Option<Vec<usize>> {
+    (!text.is_ascii()).then(|| {
+        text.char_indices()
+            .map(|(offset, _)| offset)
+            .chain(core::iter::once(text.len()))
+            .collect()
+    })
+}
+
+/// The parser reports positions in chars, everything else works with bytes.
+fn byte_offset(offsets: Option<&[usize]>, idx: usize) -> Option<usize> {
+    match offsets {
+        Some(offsets) => offsets.get(idx).copied(),
+        None => Some(idx),
+    }
+}
+
+/// End of the quoted scalar starting at `start`, just past the closing quote.
+fn quoted_end(text: &str, start: usize) -> Option<usize> {
+    let mut chars = text.get(start..)?.char_indices();
+    let (_, quote) = chars.next()?;
+
+    while let Some((offset, ch)) = chars.next() {
+        if quote == '"' && ch == '\\' {
+            chars.next();
+            continue;
+        }
+
+        if ch == quote {
+            if quote == '\''
+                && text
+                    .get(start.saturating_add(offset).saturating_add(1)..)?
+                    .starts_with('\'')
+            {
+                chars.next();
+            } else {
+                return Some(start.saturating_add(offset).saturating_add(1));
+            }
+        }
+    }
+
+    None
+}
+
+/// Number of chars between `offset` and the start of its line.
+fn column(text: &str, offset: usize) -> usize {
+    text.get(..offset)
+        .map(|before| {
+            before
+                .rsplit('\n')
+                .next()
+                .map_or(0, |line| line.chars().count())
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::{anyhow, bail};
+
+    use super::*;
+    use crate::pointer::ValuePointer;
+
+    ///
+    fn source(format: Format, text: &str) -> anyhow::Result<Source> {
+        let original = format.parse(text)?;
+
+        Source::new(format, text.to_owned(), original).ok_or_else(|| anyhow!("Unable to index"))
+    }
+
+    ///
+    fn replace(doc: &mut Value, path: &str, value: Value) -> anyhow::Result<()> {
+        *path.parse::<ValuePointer>()?.get_mut(doc)? = value;
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_scalar_replacement_keeps_comments() -> anyhow::Result<()> {
+        let text = "# header\na: 1 # one\nb:\n  c: 'quoted' # two\n  d: |\n    block\n# footer\n";
+        let source = source(Format::Yaml, text)?;
+
+        let mut doc = source.original.clone();
+        replace(&mut doc, "#/b/c", Value::String("changed".to_owned()))?;
+
+        let rendered = source.render(&doc).ok_or_else(|| anyhow!("No render"))?;
+        let expected =
+            "# header\na: 1 # one\nb:\n  c: 'changed' # two\n  d: |\n    block\n# footer\n";
+
+        if rendered != expected {
+            bail!("Expected {:?}, got {:?}", expected, rendered);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_keys_with_pointer_characters() -> anyhow::Result<()> {
+        let text = "a~1b: 1 # tilde\na/b: 2 # slash\n";
+        let source = source(Format::Yaml, text)?;
+
+        let mut doc = source.original.clone();
+        replace(&mut doc, "#/a~01b", serde_yaml::from_str("3")?)?;
+
+        let rendered = source.render(&doc).ok_or_else(|| anyhow!("No render"))?;
+        let expected = "a~1b: 3 # tilde\na/b: 2 # slash\n";
+
+        if rendered != expected {
+            bail!("Expected {:?}, got {:?}", expected, rendered);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_string_replacement_keeps_quotes() -> anyhow::Result<()> {
+        let text = "version: \"1\" # v\nname: 'x'\nplain: y\nflow: {a: 'b'}\n";
+        let source = source(Format::Yaml, text)?;
+
+        let mut doc = source.original.clone();
+        replace(&mut doc, "#/version", Value::String("2.0.0".to_owned()))?;
+        replace(&mut doc, "#/name", Value::String("it's".to_owned()))?;
+        replace(&mut doc, "#/plain", Value::String("2".to_owned()))?;
+        replace(&mut doc, "#/flow/a", Value::String("c\nd".to_owned()))?;
+
+        let rendered = source.render(&doc).ok_or_else(|| anyhow!("No render"))?;
+        let expected = "version: \"2.0.0\" # v\nname: 'it''s'\nplain: '2'\nflow: {a: \"c\\nd\"}\n";
+
+        if rendered != expected {
+            bail!("Expected {:?}, got {:?}", expected, rendered);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_nested_replacement() -> anyhow::Result<()> {
+        let text = "a:\n  - x # first\n  - y\nb: 2\n";
+        let source = source(Format::Yaml, text)?;
+
+        let mut doc = source.original.clone();
+        replace(&mut doc, "#/b", serde_yaml::from_str("{c: [1, 2]}")?)?;
+
+        let rendered = source.render(&doc).ok_or_else(|| anyhow!("No render"))?;
+        let expected = "a:\n  - x # first\n  - y\nb:\n  c:\n  - 1\n  - 2\n";
+
+        if rendered != expected {
+            bail!("Expected {:?}, got {:?}", expected, rendered);
+        }
+
+        if serde_yaml::from_str::<Value>(&rendered)? != doc {
+            bail!("Rendered document doesn't round trip: {:?}", rendered);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_swap_entries() -> anyhow::Result<()> {
+        let text = "a: 1 # one\nb:\n  c: 2\n";
+        let source = source(Format::Yaml, text)?;
+
+        let mut doc = source.original.clone();
+        if let Value::Object(ref mut map) = doc {
+            map.swap_indices(0, 1);
+        }
+
+        let rendered = source.render(&doc).ok_or_else(|| anyhow!("No render"))?;
+        let expected = "b:\n  c: 2\na: 1 # one\n";
+
+        if rendered != expected {
+            bail!("Expected {:?}, got {:?}", expected, rendered);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_swap_items_keeps_layout() -> anyhow::Result<()> {
+        let text = "{\n    \"list\": [1, {\"a\":true}],\n    \"other\": \"x\"\n}\n";
+        let source = source(Format::Json, text)?;
+
+        let mut doc = source.original.clone();
+        if let Value::Object(ref mut map) = doc {
+            if let Some(&mut Value::Array(ref mut arr)) = map.get_mut("list") {
+                arr.swap(0, 1);
+            }
+        }
+
+        let rendered = source.render(&doc).ok_or_else(|| anyhow!("No render"))?;
+        let expected = "{\n    \"list\": [{\"a\":true}, 1],\n    \"other\": \"x\"\n}\n";
+
+        if rendered != expected {
+            bail!("Expected {:?}, got {:?}", expected, rendered);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_replacement_uses_file_indent() -> anyhow::Result<()> {
+        let text = "{\n    \"a\": 1,\n    \"b\": null\n}";
+        let source = source(Format::Json, text)?;
+
+        let mut doc = source.original.clone();
+        replace(&mut doc, "#/b", serde_json::from_str(r#"{"c": [true]}"#)?)?;
+
+        let rendered = source.render(&doc).ok_or_else(|| anyhow!("No render"))?;
+        let expected =
+            "{\n    \"a\": 1,\n    \"b\": {\n        \"c\": [\n            true\n        ]\n    }\n}";
+
+        if rendered != expected {
+            bail!("Expected {:?}, got {:?}", expected, rendered);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_stream_keeps_separators() -> anyhow::Result<()> {
+        let text =
+            "# manifests\n---\nkind: A # first\n---\nkind: B\nspec:\n  replicas: 1 # scale\n";
+        let source = source(Format::YamlStream, text)?;
+
+        let mut doc = source.original.clone();
+        replace(&mut doc, "#/1/spec/replicas", serde_yaml::from_str("3")?)?;
+        if let Value::Array(ref mut documents) = doc {
+            documents.push(serde_yaml::from_str("kind: C")?);
+        }
+
+        let rendered = source.render(&doc).ok_or_else(|| anyhow!("No render"))?;
+        let expected = "# manifests\n---\nkind: A # first\n---\nkind: B\nspec:\n  replicas: 3 # scale\n---\nkind: C\n";
+
+        if rendered != expected {
+            bail!("Expected {:?}, got {:?}", expected, rendered);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json5_keeps_comments() -> anyhow::Result<()> {
+        let text = "{\n  // the answer\n  \"a\": 1,\n  \"b\": [1, 2,], /* tail */\n}\n";
+        let source = source(Format::Json5, text)?;
+
+        let mut doc = source.original.clone();
+        replace(&mut doc, "#/a", serde_yaml::from_str("2")?)?;
+
+        let rendered = source.render(&doc).ok_or_else(|| anyhow!("No render"))?;
+        let expected = "{\n  // the answer\n  \"a\": 2,\n  \"b\": [1, 2,], /* tail */\n}\n";
+
+        if rendered != expected {
+            bail!("Expected {:?}, got {:?}", expected, rendered);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_added_and_removed_entries() -> anyhow::Result<()> {
+        let text = "info:\n  version: 1.0.0 # current\n  # legacy\n  old: x\nlist:\n  - a # first\n  - b\n";
+        let source = source(Format::Yaml, text)?;
+
+        let mut doc = source.original.clone();
+        "#/info/old".parse::<ValuePointer>()?.remove(&mut doc)?;
+        "#/info/title"
+            .parse::<ValuePointer>()?
+            .set(&mut doc, Value::String("Pets".to_owned()))?;
+        "#/list/1".parse::<ValuePointer>()?.remove(&mut doc)?;
+        "#/list/-"
+            .parse::<ValuePointer>()?
+            .set(&mut doc, Value::String("c".to_owned()))?;
+
+        let rendered = source.render(&doc).ok_or_else(|| anyhow!("No render"))?;
+        let expected =
+            "info:\n  version: 1.0.0 # current\n  title: Pets\nlist:\n  - a # first\n  - c\n";
+
+        if rendered != expected {
+            bail!("Expected {:?}, got {:?}", expected, rendered);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_added_and_removed_entries() -> anyhow::Result<()> {
+        let text = "{\n    \"a\": 1,\n    \"b\": [1, 2]\n}\n";
+        let source = source(Format::Json, text)?;
+
+        let mut doc = source.original.clone();
+        "#/a".parse::<ValuePointer>()?.remove(&mut doc)?;
+        "#/b/-"
+            .parse::<ValuePointer>()?
+            .set(&mut doc, serde_yaml::from_str("3")?)?;
+        "#/c"
+            .parse::<ValuePointer>()?
+            .set(&mut doc, serde_yaml::from_str("{x: 1}")?)?;
+
+        let rendered = source.render(&doc).ok_or_else(|| anyhow!("No render"))?;
+        let expected = "{\n    \"b\": [1, 2, 3],\n    \"c\": {\n        \"x\": 1\n    }\n}\n";
+
+        if rendered != expected {
+            bail!("Expected {:?}, got {:?}", expected, rendered);
+        }
+
+        Ok(())
+    }
+}
@@ -1,18 +1,53 @@
 //!
 
-use crate::{search::PatternCache, value::Value};
+use core::str::FromStr;
+use std::collections::HashMap;
+
+use anyhow::bail;
+
+use crate::{
+    config::Config,
+    diagnostics::{self, Problem},
+    format::Format,
+    marks::Marks,
+    pointer::{escape, ValuePointer},
+    search::PatternCache,
+    util::Persisted,
+    value::Value,
+};
 
 ///
 pub const ROOT_PATH: &str = "#";
 
+/// Locations kept in the jump list, the oldest are dropped first.
+pub const MAX_JUMPS: usize = 100;
+
+/// Shown when something would change a document opened read only.
+pub const READ_ONLY_WARNING: &str = "Opened read only, the document can't be changed";
+
+/// Shown when something would change a file reached through a `$ref`.
+pub const EXTERNAL_WARNING: &str = "Referenced files are read only, go back to edit the document";
+
 ///
 pub struct State {
     ///
     pub file_name: String,
 
+    /// Contents of `file_name` as of the last load or save.
+    pub persisted: Option<Persisted>,
+
+    /// Refuse every change to the document.
+    pub read_only: bool,
+
+    /// Settings from the config file.
+    pub config: Config,
+
     ///
     pub doc: Value,
 
+    /// Files loaded to follow `$ref`s into them, by absolute path.
+    pub external: HashMap<String, Value>,
+
     ///
     pub current_page: Page,
 
@@ -37,8 +72,57 @@ pub struct State {
     ///
     pub export_prompt_state: ExportPrompt,
 
+    ///
+    pub save_as_prompt_state: SaveAsPrompt,
+
+    ///
+    pub command_line_state: CommandLine,
+
+    /// Marks of the document, saved in the state file.
+    pub marks: Marks,
+
+    ///
+    pub marks_state: MarksList,
+
+    ///
+    pub usages_state: Usages,
+
+    /// Broken `$ref`s of the document, checked again on every change.
+    pub problems: Vec<Problem>,
+
+    ///
+    pub problems_state: ProblemsList,
+
     ///
     pub status: Status,
+
+    /// Set when rover was started to pick a value for a script.
+    pub pick: Option<Pick>,
+
+    /// Pointer chosen in pick mode.
+    pub picked: Option<String>,
+}
+
+/// What pick mode writes to standard output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pick {
+    ///
+    Pointer,
+
+    ///
+    Value,
+}
+
+impl FromStr for Pick {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pointer" => Ok(Self::Pointer),
+            "value" => Ok(Self::Value),
+            _ => bail!("Unsupported pick: {s}, expected pointer or value"),
+        }
+    }
 }
 
 ///
@@ -90,27 +174,123 @@ pub struct Status {
 }
 
 impl State {
+    /// Format the document is shown and edited in.
+    pub fn format(&self) -> Format {
+        self.persisted
+            .as_ref()
+            .map_or(Format::Json, |persisted| persisted.format)
+    }
+
+    /// The referenced `file`, or the edited document for `None`.
+    pub fn document(&self, file: Option<&str>) -> &Value {
+        file.and_then(|file| self.external.get(file))
+            .unwrap_or(&self.doc)
+    }
+
+    /// Document the navigation view is in, the edited one or a file reached through a `$ref`.
+    pub fn current_doc(&self) -> &Value {
+        self.document(self.nav_state.current.file.as_deref())
+    }
+
+    /// File the navigation view is in.
+    pub fn current_file(&self) -> &str {
+        self.nav_state
+            .current
+            .file
+            .as_deref()
+            .unwrap_or(&self.file_name)
+    }
+
+    /// The `$ref` of the highlighted entry, when its value is an object with one.
+    pub fn highlighted_reference(&self) -> Option<&str> {
+        let path = &self.nav_state.current.path;
+        let index = self.nav_state.current.selected;
+
+        let value = path
+            .parse::<ValuePointer>()
+            .ok()?
+            .get(self.current_doc())
+            .ok()?;
+        if let &Value::Object(ref map) = value {
+            if let Some((_, &Value::Object(ref child))) = map.get_index(index) {
+                if let Some(&Value::String(ref reference)) = child.get("$ref") {
+                    return Some(reference);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Pointer of the item highlighted in the navigation view.
+    pub fn highlighted_path(&self) -> Option<String> {
+        let path = &self.nav_state.current.path;
+        let index = self.nav_state.current.selected;
+
+        match path
+            .parse::<ValuePointer>()
+            .ok()?
+            .get(self.current_doc())
+            .ok()?
+        {
+            &Value::Object(ref map) => map
+                .get_index(index)
+                .map(|(key, _)| format!("{path}/{}", escape(key))),
+            &Value::Array(ref array) => (index < array.len()).then(|| format!("{path}/{index}")),
+            &Value::Null
+            | &Value::Bool(_)
+            | &Value::Number(_)
+            | &Value::Bytes(_)
+            | &Value::String(_) => None,
+        }
+    }
+
     ///
-    pub fn new(doc: Value, file_name: String) -> Self {
+    pub fn new(doc: Value, file_name: String, persisted: Persisted, config: Config) -> Self {
+        let capacity = config.search.cache_capacity;
+        let weights = config.search.fuzzy;
+        let problems = diagnostics::check(&doc);
+        let status = if problems.is_empty() {
+            Status {
+                message: StatusMessage::Empty,
+                timeout: None,
+            }
+        } else {
+            Status {
+                message: StatusMessage::Warn(format!(
+                    "{} broken $refs, press P to list them",
+                    problems.len()
+                )),
+                timeout: std::time::Instant::now().checked_add(config.status_timeout()),
+            }
+        };
+
         Self {
             file_name,
+            persisted: Some(persisted),
+            read_only: false,
+            config,
             doc,
+            external: HashMap::new(),
             current_page: Page::Nav,
             needs_index: true,
             undo_stack: vec![],
             redo_stack: vec![],
             nav_state: Nav {
                 current: Step {
+                    file: None,
                     selected: 0,
                     path: ROOT_PATH.to_owned(),
                 },
                 history: vec![],
+                jumps: vec![],
+                jump_index: 0,
             },
             search_state: Search {
                 value: String::new(),
                 filtered_paths: vec![],
-                cache: PatternCache::new(1000),
-                deref_cache: PatternCache::new(1000),
+                cache: PatternCache::new(capacity).weights(weights),
+                deref_cache: PatternCache::new(capacity).weights(weights),
                 selected: 0,
             },
             import_prompt_state: ImportPrompt {
@@ -119,16 +299,38 @@ impl State {
             export_prompt_state: ExportPrompt {
                 value: String::new(),
             },
-            status: Status {
-                message: StatusMessage::Empty,
-                timeout: None,
+            save_as_prompt_state: SaveAsPrompt {
+                value: String::new(),
+                overwrite: None,
+            },
+            command_line_state: CommandLine {
+                value: String::new(),
+                history: vec![],
+                history_index: None,
             },
+            marks: Marks::new(),
+            marks_state: MarksList { selected: 0 },
+            usages_state: Usages {
+                references: vec![],
+                path: String::new(),
+                results: vec![],
+                selected: 0,
+            },
+            problems,
+            problems_state: ProblemsList { selected: 0 },
+            status,
+            pick: None,
+            picked: None,
         }
     }
 }
 
 ///
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Step {
+    /// Referenced file `path` is in, `None` for the edited document.
+    pub file: Option<String>,
+
     ///
     pub path: String,
 
@@ -143,6 +345,12 @@ pub struct Nav {
 
     ///
     pub history: Vec<Step>,
+
+    /// Locations jumped away from, by following a `$ref`, searching or going to a pointer.
+    pub jumps: Vec<Step>,
+
+    /// Position in `jumps` while going through it, its length when not.
+    pub jump_index: usize,
 }
 
 ///
@@ -176,7 +384,56 @@ pub struct ExportPrompt {
 }
 
 ///
-#[derive(Clone, Copy)]
+pub struct SaveAsPrompt {
+    ///
+    pub value: String,
+
+    /// Existing file the user was warned about, saving to it again overwrites it.
+    pub overwrite: Option<String>,
+}
+
+/// The `:` command line of the navigation view.
+pub struct CommandLine {
+    ///
+    pub value: String,
+
+    /// Lines run before, oldest first.
+    pub history: Vec<String>,
+
+    /// Position in `history` while going through it with up and down.
+    pub history_index: Option<usize>,
+}
+
+/// The list of marks.
+pub struct MarksList {
+    ///
+    pub selected: usize,
+}
+
+/// The `$ref`s found pointing at a value.
+pub struct Usages {
+    /// Every `$ref` into the document, as the path of its map and its target, rebuilt along
+    /// with the search index.
+    pub references: Vec<(String, String)>,
+
+    /// Path the usages were looked up for.
+    pub path: String,
+
+    ///
+    pub results: Vec<String>,
+
+    ///
+    pub selected: usize,
+}
+
+/// The list of broken `$ref`s.
+pub struct ProblemsList {
+    ///
+    pub selected: usize,
+}
+
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Page {
     ///
     Nav,
@@ -189,4 +446,64 @@ pub enum Page {
 
     ///
     ExportPrompt,
+
+    ///
+    SaveAsPrompt,
+
+    /// The `:` command line, shown over the navigation view.
+    CommandLine,
+
+    /// The list of marks, to jump to or delete them.
+    Marks,
+
+    /// The `$ref`s pointing at a value.
+    Usages,
+
+    /// The broken `$ref`s of the document.
+    Problems,
+}
+
+#[cfg(test)]
+pub mod test {
+    use anyhow::bail;
+
+    use super::*;
+    use crate::{format::Datetimes, util::Fingerprint};
+
+    /// State of a YAML document that was never saved, with the default configuration.
+    pub fn state(doc: Value) -> State {
+        let persisted = Persisted {
+            fingerprint: Fingerprint::of(b""),
+            format: Format::Yaml,
+            compression: None,
+            source: None,
+            comments: false,
+            datetimes: Datetimes::new(),
+        };
+
+        State::new(doc, "spec.yaml".to_owned(), persisted, Config::default())
+    }
+
+    #[test]
+    fn test_highlighted_path() -> anyhow::Result<()> {
+        let doc: Value = serde_yaml::from_str("paths:\n  /pets~v2: {}\n  /owners: {}\n")?;
+        let mut state = state(doc);
+        state.nav_state.current.path = "#/paths".to_owned();
+
+        let cases = [
+            (0, Some("#/paths/~1pets~0v2")),
+            (1, Some("#/paths/~1owners")),
+            (2, None),
+        ];
+        for (selected, expected) in cases {
+            state.nav_state.current.selected = selected;
+
+            let path = state.highlighted_path();
+            if path.as_deref() != expected {
+                bail!("Expected {:?}, got {:?}", expected, path);
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -2,8 +2,15 @@
 
 //!
 
-use alloc::borrow::Cow;
-use std::{ffi::OsStr, io, path::PathBuf};
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{self, IsTerminal as _},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
 
 use crossterm::{
     event::EnableMouseCapture,
@@ -20,17 +27,32 @@ use tui::{
 };
 
 use crate::{
-    pointer::ValuePointer,
+    diagnostics::Problem,
+    pointer::{escape, ValuePointer},
     state::{Page, State, Step},
     value::Value,
 };
 
-///
-pub fn configure_terminal() -> anyhow::Result<Terminal<CrosstermBackend<io::Stdout>>> {
+/// Where the interface is drawn.
+pub type Output = Box<dyn io::Write + Send + Sync>;
+
+/// Draws to standard output, or straight to the terminal when standard output
+/// is redirected so the document can be written there on exit.
+pub fn configure_terminal() -> anyhow::Result<Terminal<CrosstermBackend<Output>>> {
+    let mut output: Output = if io::stdout().is_terminal() {
+        Box::new(io::stdout())
+    } else {
+        Box::new(
+            OpenOptions::new()
+                .write(true)
+                .open("/dev/tty")
+                .context("Unable to open the terminal")?,
+        )
+    };
+
     enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
+    execute!(output, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(output);
     let terminal = Terminal::new(backend)?;
 
     Ok(terminal)
@@ -39,67 +61,49 @@ pub fn configure_terminal() -> anyhow::Result<Terminal<CrosstermBackend<io::Stdo
 ///
 pub fn ui<B: Backend>(frame: &mut Frame<B>, state: &State) {
     match state.current_page {
-        Page::Nav => nav(frame, state),
+        Page::Nav | Page::CommandLine => nav(frame, state),
         Page::Search => search(frame, state),
         Page::ImportPrompt => import_prompt(frame, state),
         Page::ExportPrompt => export_prompt(frame, state),
+        Page::SaveAsPrompt => save_as_prompt(frame, state),
+        Page::Marks => marks(frame, state),
+        Page::Usages => usages(frame, state),
+        Page::Problems => problems(frame, state),
     }
 }
 
 ///
 pub fn import_prompt<B: Backend>(frame: &mut Frame<B>, state: &State) {
-    let horizontal = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints(
-            [
-                Constraint::Ratio(1, 4),
-                Constraint::Ratio(2, 4),
-                Constraint::Ratio(1, 4),
-            ]
-            .as_ref(),
-        )
-        .split(frame.size());
-
-    if let Some(column) = horizontal.get(1) {
-        let vertical_margin = column.height.saturating_sub(4).saturating_div(2);
-
-        let vertical = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints(
-                [
-                    Constraint::Length(vertical_margin),
-                    Constraint::Length(4),
-                    Constraint::Length(vertical_margin),
-                ]
-                .as_ref(),
-            )
-            .split(*column);
-
-        if let Some(row) = vertical.get(1) {
-            let prompt = Block::default().title("Import").borders(Borders::ALL);
+    prompt(
+        frame,
+        "Import",
+        "  Select the file path to import into the current document:",
+        &state.import_prompt_state.value,
+    );
+}
 
-            let text = Paragraph::new(Text::from(vec![
-                Spans::from(Span::styled(
-                    "  Select the file path to import into the current document:",
-                    Style::default()
-                        .add_modifier(Modifier::BOLD)
-                        .fg(Color::White),
-                )),
-                Spans::from(vec![
-                    Span::raw(" > "),
-                    Span::raw(state.import_prompt_state.value.clone()),
-                    Span::styled("_", Style::default().bg(Color::White)),
-                ]),
-            ]))
-            .block(prompt);
+///
+pub fn export_prompt<B: Backend>(frame: &mut Frame<B>, state: &State) {
+    prompt(
+        frame,
+        "Export",
+        "  Select the file path to export the current document to:",
+        &state.export_prompt_state.value,
+    );
+}
 
-            frame.render_widget(text, *row);
-        }
-    }
+///
+pub fn save_as_prompt<B: Backend>(frame: &mut Frame<B>, state: &State) {
+    prompt(
+        frame,
+        "Save As",
+        "  Select the file path to save the document to:",
+        &state.save_as_prompt_state.value,
+    );
 }
 
 ///
-pub fn export_prompt<B: Backend>(frame: &mut Frame<B>, state: &State) {
+fn prompt<B: Backend>(frame: &mut Frame<B>, title: &str, message: &str, value: &str) {
     let horizontal = Layout::default()
         .direction(Direction::Horizontal)
         .constraints(
@@ -128,18 +132,20 @@ pub fn export_prompt<B: Backend>(frame: &mut Frame<B>, state: &State) {
             .split(*column);
 
         if let Some(row) = vertical.get(1) {
-            let prompt = Block::default().title("Export").borders(Borders::ALL);
+            let prompt = Block::default()
+                .title(title.to_owned())
+                .borders(Borders::ALL);
 
             let text = Paragraph::new(Text::from(vec![
                 Spans::from(Span::styled(
-                    "  Select the file path to export the current document to:",
+                    message.to_owned(),
                     Style::default()
                         .add_modifier(Modifier::BOLD)
                         .fg(Color::White),
                 )),
                 Spans::from(vec![
                     Span::raw(" > "),
-                    Span::raw(state.export_prompt_state.value.clone()),
+                    Span::raw(value.to_owned()),
                     Span::styled("_", Style::default().bg(Color::White)),
                 ]),
             ]))
@@ -189,7 +195,12 @@ pub fn nav<B: Backend>(frame: &mut Frame<B>, state: &State) {
         if let Some(rect) = chunks.next() {
             let previous = Block::default().title("Previous").borders(Borders::ALL);
             if let Some(prev) = state.nav_state.history.last() {
-                let (list, mut state) = step_list(prev, &state.doc, previous);
+                let (list, mut state) = step_list(
+                    prev,
+                    state.document(prev.file.as_deref()),
+                    step_problems(state, prev),
+                    previous,
+                );
                 frame.render_stateful_widget(list, rect, &mut state);
             } else {
                 frame.render_widget(previous, rect);
@@ -198,8 +209,13 @@ pub fn nav<B: Backend>(frame: &mut Frame<B>, state: &State) {
 
         if let Some(rect) = chunks.next() {
             let current = Block::default().title("Current").borders(Borders::ALL);
-            let (current, mut current_state) =
-                step_list(&state.nav_state.current, &state.doc, current);
+            let current_step = &state.nav_state.current;
+            let (current, mut current_state) = step_list(
+                current_step,
+                state.current_doc(),
+                step_problems(state, current_step),
+                current,
+            );
             frame.render_stateful_widget(current, rect, &mut current_state);
         }
 
@@ -210,12 +226,7 @@ pub fn nav<B: Backend>(frame: &mut Frame<B>, state: &State) {
                 .path
                 .parse::<ValuePointer>()
                 .ok()
-                .and_then(|path| path.get(&state.doc).ok());
-
-            let extension = PathBuf::from(&state.file_name);
-            let extension = extension
-                .extension()
-                .map_or(Cow::Borrowed("json"), OsStr::to_string_lossy);
+                .and_then(|path| path.get(state.current_doc()).ok());
 
             if let Some(selected_path) = selected_path {
                 let selected_path = match selected_path {
@@ -223,13 +234,13 @@ pub fn nav<B: Backend>(frame: &mut Frame<B>, state: &State) {
                     &Value::Object(ref object) => object
                         .get_index(state.nav_state.current.selected)
                         .map(|(_, value)| value),
-                    &Value::Null | &Value::Bool(_) | &Value::String(_) | &Value::Number(_) => None,
+                    &Value::Null
+                    | &Value::Bool(_)
+                    | &Value::String(_)
+                    | &Value::Number(_)
+                    | &Value::Bytes(_) => None,
                 };
-                let selected_path = selected_path.and_then(|value| match extension.as_ref() {
-                    "yaml" | "yml" => serde_yaml::to_string(value).ok(),
-                    "json" => serde_json::to_string_pretty(value).ok(),
-                    _ => None,
-                });
+                let selected_path = selected_path.and_then(|value| preview(state, value));
 
                 if let Some(selected_path) = selected_path {
                     let text: Vec<Spans> = selected_path
@@ -256,10 +267,46 @@ pub fn nav<B: Backend>(frame: &mut Frame<B>, state: &State) {
     }
 
     if let Some(rect) = main_chunks.next() {
-        frame.render_widget(status(state), rect);
+        if state.current_page == Page::CommandLine {
+            frame.render_widget(command_line(state), rect);
+        } else {
+            frame.render_widget(status(state), rect);
+        }
     }
 }
 
+/// The `:` command line, shown in place of the status bar.
+fn command_line<'line>(state: &State) -> Paragraph<'line> {
+    let block = Block::default().title("Command").borders(Borders::ALL);
+
+    Paragraph::new(Spans::from(vec![
+        Span::raw(":"),
+        Span::raw(state.command_line_state.value.clone()),
+        Span::styled("_", Style::default().bg(Color::White)),
+    ]))
+    .block(block)
+}
+
+/// Text shown in the preview pane when `value` is selected, byte strings are dumped as hex.
+fn preview(state: &State, value: &Value) -> Option<String> {
+    let &Value::Bytes(ref bytes) = value else {
+        return state.format().fragment().serialize(value).ok();
+    };
+
+    let mut lines: Vec<String> = bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{byte:02x}")).collect();
+            format!("{:08x}  {}", line.saturating_mul(16), hex.join(" "))
+        })
+        .collect();
+    lines.push(String::new());
+    lines.push(format!("base64: {}", STANDARD.encode(bytes)));
+
+    Some(lines.join("\n"))
+}
+
 ///
 fn status<'status>(state: &State) -> Paragraph<'status> {
     let location = Block::default().title("Status").borders(Borders::ALL);
@@ -289,13 +336,50 @@ fn status<'status>(state: &State) -> Paragraph<'status> {
 
 ///
 fn current_path<'path>(state: &State) -> Paragraph<'path> {
-    let location = Block::default().title("Location").borders(Borders::ALL);
+    let title = if state.read_only {
+        "Location [RO]"
+    } else {
+        "Location"
+    };
+    let location = Block::default().title(title).borders(Borders::ALL);
+
+    let current = &state.nav_state.current;
+    let text = current.file.as_ref().map_or_else(
+        || current.path.clone(),
+        |file| {
+            // Show referenced files relative to the working directory when they are below it
+            let file = env::current_dir()
+                .ok()
+                .and_then(|dir| {
+                    Path::new(file)
+                        .strip_prefix(dir)
+                        .ok()
+                        .map(Path::to_path_buf)
+                })
+                .unwrap_or_else(|| PathBuf::from(file));
+            format!("{}{}", file.display(), current.path)
+        },
+    );
+
+    Paragraph::new(Text::raw(text)).block(location)
+}
+
+/// Broken `$ref`s that can show up in `step`, only the document itself is checked.
+fn step_problems<'state>(state: &'state State, step: &Step) -> &'state [Problem] {
+    if step.file.is_some() {
+        return &[];
+    }
 
-    Paragraph::new(Text::raw(state.nav_state.current.path.clone())).block(location)
+    &state.problems
 }
 
-///
-fn step_list<'list>(step: &Step, doc: &Value, parent: Block<'list>) -> (List<'list>, ListState) {
+/// Items of the value at `step`, the ones holding a broken `$ref` marked with a `!`.
+fn step_list<'list>(
+    step: &Step,
+    doc: &Value,
+    problems: &[Problem],
+    parent: Block<'list>,
+) -> (List<'list>, ListState) {
     let prev_items: Vec<ListItem> = step
         .path
         .parse::<ValuePointer>()
@@ -305,14 +389,30 @@ fn step_list<'list>(step: &Step, doc: &Value, parent: Block<'list>) -> (List<'li
             &Value::Array(ref array) => array
                 .iter()
                 .enumerate()
-                .map(|(i, _)| i.to_string())
+                .map(|(i, _)| (i.to_string(), i.to_string()))
+                .collect(),
+            &Value::Object(ref object) => object
+                .keys()
+                .map(|key| (key.clone(), escape(key)))
                 .collect(),
-            &Value::Object(ref object) => object.keys().cloned().collect(),
-            &Value::Null | &Value::Bool(_) | &Value::String(_) | &Value::Number(_) => vec![],
+            &Value::Null
+            | &Value::Bool(_)
+            | &Value::String(_)
+            | &Value::Number(_)
+            | &Value::Bytes(_) => vec![],
         })
-        .map(|name| {
-            name.into_iter()
-                .map(|name| ListItem::new(Text::raw(name)))
+        .map(|names| {
+            names
+                .into_iter()
+                .map(|(name, token)| {
+                    let path = format!("{}/{token}", step.path);
+                    if problems.iter().any(|problem| problem.path == path) {
+                        ListItem::new(Text::raw(format!("{name} !")))
+                            .style(Style::default().fg(Color::Red))
+                    } else {
+                        ListItem::new(Text::raw(name))
+                    }
+                })
                 .collect()
         })
         .unwrap_or_default();
@@ -415,21 +515,12 @@ fn search<B: Backend>(frame: &mut Frame<B>, state: &State) {
                 .filtered_paths
                 .get(state.search_state.selected);
 
-            let extension = PathBuf::from(&state.file_name);
-            let extension = extension
-                .extension()
-                .map_or(Cow::Borrowed("json"), OsStr::to_string_lossy);
-
             if let Some(selected_path) = selected_path {
                 let selected_path = selected_path
                     .strip_prefix('#')
                     .and_then(|path| path.parse::<ValuePointer>().ok())
                     .and_then(|path| path.get(&state.doc).ok())
-                    .and_then(|value| match extension.as_ref() {
-                        "yaml" | "yml" => serde_yaml::to_string(value).ok(),
-                        "json" => serde_json::to_string_pretty(value).ok(),
-                        _ => None,
-                    });
+                    .and_then(|value| preview(state, value));
                 if let Some(selected_path) = selected_path {
                     let text: Vec<Spans> = selected_path
                         .split('\n')
@@ -458,3 +549,130 @@ fn search<B: Backend>(frame: &mut Frame<B>, state: &State) {
         frame.render_widget(status(state), rect);
     }
 }
+
+/// The list of marks next to a preview of the selected one.
+fn marks<B: Backend>(frame: &mut Frame<B>, state: &State) {
+    let items = state
+        .marks
+        .iter()
+        .map(|(mark, path)| ListItem::new(Text::raw(format!("'{mark}  {path}"))))
+        .collect();
+    let selected = state.marks.values().nth(state.marks_state.selected);
+
+    path_list(
+        frame,
+        state,
+        format!("Marks ({})", state.marks.len()),
+        items,
+        (state.marks_state.selected, selected),
+    );
+}
+
+/// The `$ref`s pointing at a value, next to a preview of the selected one.
+fn usages<B: Backend>(frame: &mut Frame<B>, state: &State) {
+    let usages = &state.usages_state;
+    let items = usages
+        .results
+        .iter()
+        .map(|path| ListItem::new(Text::raw(path.clone())))
+        .collect();
+
+    path_list(
+        frame,
+        state,
+        format!("Usages of {} ({})", usages.path, usages.results.len()),
+        items,
+        (usages.selected, usages.results.get(usages.selected)),
+    );
+}
+
+/// The broken `$ref`s of the document, next to a preview of the selected one.
+fn problems<B: Backend>(frame: &mut Frame<B>, state: &State) {
+    let items = state
+        .problems
+        .iter()
+        .map(|problem| ListItem::new(Text::raw(format!("{}  {problem}", problem.path))))
+        .collect();
+    let selected = state
+        .problems
+        .get(state.problems_state.selected)
+        .map(|problem| &problem.path);
+
+    path_list(
+        frame,
+        state,
+        format!("Problems ({})", state.problems.len()),
+        items,
+        (state.problems_state.selected, selected),
+    );
+}
+
+/// A list of `items` with the index and path of the selected one, previewed beside it.
+fn path_list<B: Backend>(
+    frame: &mut Frame<B>,
+    state: &State,
+    title: String,
+    items: Vec<ListItem>,
+    (selected, selected_path): (usize, Option<&String>),
+) {
+    let mut chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints(
+            [
+                Constraint::Length(frame.size().height.saturating_sub(5)),
+                Constraint::Length(3),
+            ]
+            .as_ref(),
+        )
+        .split(frame.size())
+        .into_iter();
+
+    if let Some(rect) = chunks.next() {
+        let mut list_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)].as_ref())
+            .split(rect)
+            .into_iter();
+
+        if let Some(rect) = list_chunks.next() {
+            let list = Block::default().title(title).borders(Borders::ALL);
+            let list = List::new(items)
+                .highlight_symbol("> ")
+                .highlight_style(
+                    Style::default()
+                        .add_modifier(Modifier::BOLD)
+                        .fg(Color::Yellow),
+                )
+                .block(list);
+
+            let mut list_selected = ListState::default();
+            list_selected.select(Some(selected));
+
+            frame.render_stateful_widget(list, rect, &mut list_selected);
+        }
+
+        if let Some(rect) = list_chunks.next() {
+            let preview_block = Block::default().title("Preview").borders(Borders::ALL);
+            let selected = selected_path
+                .and_then(|path| path.parse::<ValuePointer>().ok())
+                .and_then(|pointer| pointer.get(&state.doc).ok())
+                .and_then(|value| preview(state, value));
+
+            if let Some(selected) = selected {
+                let text: Vec<Spans> = selected
+                    .split('\n')
+                    .map(|line| Spans::from(Span::from(line)))
+                    .collect();
+                frame.render_widget(Paragraph::new(text).block(preview_block), rect);
+            } else {
+                // The path points at a value that is gone
+                frame.render_widget(preview_block, rect);
+            }
+        }
+    }
+
+    if let Some(rect) = chunks.next() {
+        frame.render_widget(status(state), rect);
+    }
+}
@@ -1,111 +1,531 @@
 //!
 
+use core::hash::{Hash as _, Hasher as _};
 use std::{
+    collections::hash_map::DefaultHasher,
     env,
     fs::{self, File},
-    io::Write as _,
-    path::PathBuf,
-    process::Command,
+    io::{self, Read as _, Write as _},
+    path::{Path, PathBuf},
+    process::{self, Command},
 };
 
-use anyhow::{anyhow, bail};
+use anyhow::{anyhow, bail, Context as _};
 
-use crate::value::Value;
+use crate::{
+    compression::Compression,
+    format::{Datetimes, Format},
+    source::Source,
+    value::Value,
+};
 
-///
-pub fn fetch_document(file_name: &str) -> anyhow::Result<Value> {
-    let doc = fs::read_to_string(file_name)?;
+/// Hash of a file's contents, used to detect changes made on disk
+/// between loading a document and saving it back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fingerprint(u64);
 
-    let extention = PathBuf::from(file_name);
-    let extention = extention
-        .extension()
-        .ok_or_else(|| anyhow!("File Extension not found"))?
-        .to_string_lossy();
+impl Fingerprint {
+    ///
+    pub fn of(bytes: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        Self(hasher.finish())
+    }
 
-    match extention.as_ref() {
-        "yaml" | "yml" => {
-            let doc = serde_yaml::from_str(&doc)?;
-            Ok(doc)
+    /// Fingerprint of the file currently on disk, `None` if it doesn't exist.
+    pub fn read(path: &Path) -> anyhow::Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(Self::of(&bytes))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
         }
-        "json" => {
-            let doc = serde_json::from_str(&doc)?;
-            Ok(doc)
+    }
+}
+
+/// File name used for a document read from standard input.
+pub const STDIN: &str = "-";
+
+/// Splits a `file.yaml#/pointer` argument into the file and the pointer. The
+/// argument is taken as a plain file name when a file by that name exists.
+pub fn split_fragment(argument: &str) -> (&str, Option<&str>) {
+    if argument == STDIN || Path::new(argument).exists() {
+        return (argument, None);
+    }
+
+    match argument.find('#') {
+        Some(idx) => {
+            let (file_name, pointer) = argument.split_at(idx);
+            if file_name == STDIN || Path::new(file_name).exists() {
+                (file_name, Some(pointer))
+            } else {
+                (argument, None)
+            }
         }
-        _ => bail!("File Extension not supported"),
+        None => (argument, None),
     }
 }
 
+/// What is known about a document's file as it currently is on disk.
+#[derive(Clone)]
+pub struct Persisted {
+    ///
+    pub fingerprint: Fingerprint,
+
+    /// Format the document was read in.
+    pub format: Format,
+
+    /// Compression the file is wrapped in.
+    pub compression: Option<Compression>,
+
+    /// Original text, used to preserve formatting when saving.
+    pub source: Option<Source>,
+
+    /// The file has comments that saving won't be able to keep.
+    pub comments: bool,
+
+    ///
+    pub datetimes: Datetimes,
+}
+
+/// Whether `file_name` can be saved back in place, standard input always can be
+/// through save as.
+pub fn is_writable(file_name: &str) -> bool {
+    file_name == STDIN || fs::OpenOptions::new().append(true).open(file_name).is_ok()
+}
+
+/// Whether writing `value` in `format` drops comments of the file `persisted` describes.
+pub fn drops_comments(value: &Value, persisted: &Persisted, format: Format) -> bool {
+    persisted.comments
+        || persisted.source.as_ref().is_some_and(|source| {
+            source.format() == format && source.has_comments() && source.render(value).is_none()
+        })
+}
+
+/// Encodes `value`, keeping the layout of `source` when it is in the same format.
 ///
-pub fn save_doc(file_name: &str, value: &Value) -> anyhow::Result<()> {
-    let mut path = PathBuf::from(file_name);
+/// Refuses to rewrite a document with comments that can't be kept, leave out
+/// `source` to write it without them.
+pub fn render_doc(
+    value: &Value,
+    source: Option<&Source>,
+    format: Format,
+    datetimes: &Datetimes,
+) -> anyhow::Result<Vec<u8>> {
+    let source = source.filter(|source| source.format() == format);
 
-    let stem = path
-        .file_stem()
-        .ok_or_else(|| anyhow!("File Stem not found"))?
-        .to_string_lossy();
+    match source.map(|source| (source, source.render(value))) {
+        Some((_, Some(patched))) => Ok(patched.into_bytes()),
+        Some((source, None)) if source.has_comments() => {
+            bail!("Unable to keep the comments of the document, it would have to be rewritten")
+        }
+        Some((_, None)) | None => format.encode_document(value, datetimes),
+    }
+}
 
-    let extention = path
-        .extension()
-        .ok_or_else(|| anyhow!("File Extension not found"))?
-        .to_string_lossy();
+/// Reads a document from `file_name`, or from standard input when it is [`STDIN`].
+///
+/// Unless `format` is given it is worked out from the file name, or the
+/// contents when the file name doesn't tell. Compressed files are decompressed
+/// first and their format is taken from the extension inside the compression
+/// one, so `spec.json.gz` is read as JSON.
+pub fn fetch_document(
+    file_name: &str,
+    format: Option<Format>,
+) -> anyhow::Result<(Value, Persisted)> {
+    parse_document(file_name, &read_input(file_name)?, format)
+}
 
-    let data = match extention.as_ref() {
-        "yaml" | "yml" => serde_yaml::to_string(&value)?,
-        "json" => serde_json::to_string_pretty(&value)?,
-        _ => bail!("File Extension not supported"),
+/// The bytes of `file_name` as they are on disk, or standard input when it is [`STDIN`].
+pub fn read_input(file_name: &str) -> anyhow::Result<Vec<u8>> {
+    if file_name == STDIN {
+        let mut data = Vec::new();
+        io::stdin()
+            .read_to_end(&mut data)
+            .context("Unable to read standard input")?;
+        Ok(data)
+    } else {
+        fs::read(file_name).with_context(|| format!("Unable to read {file_name}"))
+    }
+}
+
+/// Reads a document from `data`, the bytes [`read_input`] got from `file_name`,
+/// like [`fetch_document`] does.
+pub fn parse_document(
+    file_name: &str,
+    data: &[u8],
+    format: Option<Format>,
+) -> anyhow::Result<(Value, Persisted)> {
+    let fingerprint = Fingerprint::of(data);
+    let compression = Compression::detect(file_name, data);
+    let data = match compression {
+        Some(compression) => compression.decompress(data)?,
+        None => data.to_vec(),
     };
 
-    path.set_file_name(format!("new_{stem}.{extention}"));
+    let format = format.map_or_else(
+        || Format::detect(Compression::strip(file_name), &data),
+        |format| format.narrow(&data),
+    );
+    let (value, datetimes) = format.decode_document(&data)?;
 
-    let mut save_file = File::create(&path)?;
-    save_file.write_all(data.as_bytes())?;
+    let text = String::from_utf8(data).ok();
+    let comments = text
+        .as_deref()
+        .is_some_and(|text| format.has_comments(text));
+    let source = text.and_then(|text| Source::new(format, text, value.clone()));
+    if source.is_none() && !format.is_binary() {
+        log::warn!("Unable to track the layout of {file_name}, saving will reformat it");
+    }
 
-    Ok(())
+    Ok((
+        value,
+        Persisted {
+            fingerprint,
+            format,
+            compression,
+            comments: comments && source.is_none(),
+            source,
+            datetimes,
+        },
+    ))
 }
 
+/// Writes `value` to `file_name` in place.
 ///
-pub fn editor(value: &Value, file_path: &str) -> anyhow::Result<Value> {
-    let editor = env::var("EDITOR")?;
-    let mut temp_file_path = env::temp_dir();
+/// When `source` matches `format` only the edited nodes are rewritten,
+/// everything else is kept byte for byte. When `expected` is set the save is
+/// refused if the file on disk no longer matches it, so changes made by someone
+/// else are never overwritten. The document is compressed with `compression`
+/// after it is rendered.
+#[expect(
+    clippy::too_many_arguments,
+    reason = "everything that decides how the file is written"
+)]
+pub fn save_doc(
+    file_name: &str,
+    value: &Value,
+    format: Format,
+    compression: Option<Compression>,
+    source: Option<&Source>,
+    datetimes: &Datetimes,
+    expected: Option<Fingerprint>,
+    backup: bool,
+) -> anyhow::Result<Persisted> {
+    if file_name == STDIN {
+        bail!("A file name is required to save a document read from standard input");
+    }
 
-    let file_name = PathBuf::from(file_path);
-    let file_name = file_name
-        .file_name()
-        .ok_or_else(|| anyhow!("File Name not found"))?;
+    let path = PathBuf::from(file_name);
+    let data = render_doc(value, source, format, datetimes)?;
+    let written = match compression {
+        Some(compression) => compression.compress(&data)?,
+        None => data.clone(),
+    };
 
-    temp_file_path.push(file_name);
+    if let Some(expected) = expected {
+        if Fingerprint::read(&path)?.is_some_and(|current| current != expected) {
+            bail!("{file_name} was changed on disk since it was loaded");
+        }
+    }
 
-    let extension = PathBuf::from(file_path);
-    let extension = extension
-        .extension()
-        .ok_or_else(|| anyhow!("File Extension not found"))?
+    write_atomic(&path, &written, backup)?;
+
+    Ok(Persisted {
+        fingerprint: Fingerprint::of(&written),
+        format,
+        compression,
+        source: String::from_utf8(data)
+            .ok()
+            .and_then(|text| Source::new(format, text, value.clone())),
+        comments: false,
+        datetimes: datetimes.clone(),
+    })
+}
+
+/// Writes to a temporary file next to `path` and renames it into place so
+/// readers never observe a partially written document.
+fn write_atomic(path: &Path, data: &[u8], backup: bool) -> anyhow::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("File Name not found"))?
         .to_string_lossy();
 
-    let mut new_file = File::create(&temp_file_path)?;
+    let temp_path = dir.join(format!(".{file_name}.rover-{}", process::id()));
 
-    match extension.as_ref() {
-        "yaml" | "yml" => {
-            new_file.write_all(serde_yaml::to_string(&value)?.as_bytes())?;
+    if let Err(err) = write_synced(&temp_path, path, data) {
+        if let Err(err) = fs::remove_file(&temp_path) {
+            log::warn!("Unable to remove temporary file: {err}");
         }
-        "json" => {
-            new_file.write_all(serde_json::to_string_pretty(&value)?.as_bytes())?;
+        return Err(err);
+    }
+
+    if backup && path.exists() {
+        fs::copy(path, dir.join(format!("{file_name}.bak")))
+            .context("Unable to create backup file")?;
+    }
+
+    if let Err(err) = fs::rename(&temp_path, path) {
+        if let Err(err) = fs::remove_file(&temp_path) {
+            log::warn!("Unable to remove temporary file: {err}");
         }
-        _ => bail!("File Extension not supported"),
+        return Err(err.into());
+    }
+
+    // Persist the rename itself, not every platform supports syncing a directory
+    if let Err(err) = File::open(dir).and_then(|dir| dir.sync_all()) {
+        log::debug!("Unable to sync directory: {err}");
+    }
+
+    Ok(())
+}
+
+///
+fn write_synced(temp_path: &Path, original: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let mut temp_file = File::create(temp_path)?;
+    temp_file.write_all(data)?;
+    temp_file.sync_all()?;
+
+    if let Ok(metadata) = fs::metadata(original) {
+        fs::set_permissions(temp_path, metadata.permissions())?;
     }
 
+    Ok(())
+}
+
+///
+pub fn editor(value: &Value, file_path: &str, format: Format) -> anyhow::Result<Value> {
+    let editor = env::var("EDITOR")?;
+    let mut temp_file_path = env::temp_dir();
+
+    // Editors pick their highlighting from the extension, so make sure there is one
+    let file_name = if file_path == STDIN {
+        "stdin".to_owned()
+    } else {
+        PathBuf::from(Compression::strip(file_path))
+            .file_name()
+            .ok_or_else(|| anyhow!("File Name not found"))?
+            .to_string_lossy()
+            .into_owned()
+    };
+    if Format::from_path(&file_name) == Some(format) {
+        temp_file_path.push(file_name);
+    } else {
+        temp_file_path.push(format!("{file_name}.{format}"));
+    }
+
+    let mut new_file = File::create(&temp_file_path)?;
+    new_file.write_all(format.serialize(value)?.as_bytes())?;
+
     Command::new(editor).arg(&temp_file_path).status()?;
 
     let new_value = fs::read_to_string(&temp_file_path)?;
 
-    match extension.as_ref() {
-        "yaml" | "yml" => {
-            let result = serde_yaml::from_str(&new_value)?;
-            Ok(result)
+    format.parse(&new_value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    ///
+    fn scratch_file(name: &str, contents: &str) -> anyhow::Result<PathBuf> {
+        let dir = env::temp_dir().join(format!("rover-util-{}-{name}", process::id()));
+        fs::create_dir_all(&dir)?;
+
+        let path = dir.join(name);
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    #[test]
+    fn test_save_in_place() -> anyhow::Result<()> {
+        let path = scratch_file("in_place.json", r#"{"a": 1}"#)?;
+        let file_name = path.to_string_lossy();
+
+        let (mut doc, persisted) = fetch_document(&file_name, None)?;
+        if let Value::Object(ref mut map) = doc {
+            map.insert("b".to_owned(), Value::Bool(true));
+        }
+
+        let saved = save_doc(
+            &file_name,
+            &doc,
+            persisted.format,
+            persisted.compression,
+            persisted.source.as_ref(),
+            &persisted.datetimes,
+            Some(persisted.fingerprint),
+            true,
+        )?;
+
+        if Fingerprint::read(&path)? != Some(saved.fingerprint) {
+            bail!("Expected saved fingerprint to match the file on disk");
+        }
+
+        let (reloaded, _) = fetch_document(&file_name, None)?;
+        if reloaded != doc {
+            bail!("Expected {:?}, got {:?}", doc, reloaded);
+        }
+
+        let backup = fs::read_to_string(path.with_extension("json.bak"))?;
+        if backup != r#"{"a": 1}"# {
+            bail!("Expected backup of the original contents, got {:?}", backup);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_keeps_comments() -> anyhow::Result<()> {
+        // Comments on their own line and after a value are both lost by a rewrite
+        let texts = [
+            ("aliased.yaml", "# Shared\na: &x 1\nb: *x\n"),
+            ("aliased-trailing.yaml", "a: &x 1 # important\nb: *x\n"),
+        ];
+
+        for (name, text) in texts {
+            let path = scratch_file(name, text)?;
+            let file_name = path.to_string_lossy();
+
+            let (mut doc, persisted) = fetch_document(&file_name, None)?;
+            if drops_comments(&doc, &persisted, persisted.format) {
+                bail!("Expected the unchanged {:?} to keep its comments", text);
+            }
+
+            // The alias can't follow the anchor once they differ, so the file is rewritten
+            if let Value::Object(ref mut map) = doc {
+                map.insert("a".to_owned(), Value::String("2".to_owned()));
+            }
+            if !drops_comments(&doc, &persisted, persisted.format) {
+                bail!("Expected saving {:?} to drop the comments", text);
+            }
+            if let Ok(data) = render_doc(
+                &doc,
+                persisted.source.as_ref(),
+                persisted.format,
+                &persisted.datetimes,
+            ) {
+                bail!(
+                    "Expected rendering {:?} to be refused, got {:?}",
+                    text,
+                    String::from_utf8(data)
+                );
+            }
+
+            let data = render_doc(&doc, None, persisted.format, &persisted.datetimes)?;
+            let expected = "a: '2'\nb: 1\n";
+            if data != expected.as_bytes() {
+                bail!("Expected {:?}, got {:?}", expected, String::from_utf8(data));
+            }
         }
-        "json" => {
-            let result = serde_json::from_str(&new_value)?;
-            Ok(result)
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_refuses_changed_file() -> anyhow::Result<()> {
+        let path = scratch_file("changed.yaml", "a: 1\n")?;
+        let file_name = path.to_string_lossy();
+
+        let (doc, persisted) = fetch_document(&file_name, None)?;
+        fs::write(&path, "a: 2\n")?;
+
+        if save_doc(
+            &file_name,
+            &doc,
+            persisted.format,
+            persisted.compression,
+            None,
+            &persisted.datetimes,
+            Some(persisted.fingerprint),
+            false,
+        )
+        .is_ok()
+        {
+            bail!("Expected save to be refused");
+        }
+
+        let contents = fs::read_to_string(&path)?;
+        if contents != "a: 2\n" {
+            bail!("Expected file to be untouched, got {:?}", contents);
         }
-        _ => bail!("File Extension not supported"),
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_compressed() -> anyhow::Result<()> {
+        let path = scratch_file("archived.yaml.gz", "")?;
+        fs::write(&path, Compression::Gzip.compress(b"a: 1 # kept\nb: 2\n")?)?;
+        let file_name = path.to_string_lossy();
+
+        let (mut doc, persisted) = fetch_document(&file_name, None)?;
+        if persisted.format != Format::Yaml || persisted.compression != Some(Compression::Gzip) {
+            bail!(
+                "Expected gzipped YAML, got {:?} {:?}",
+                persisted.format,
+                persisted.compression
+            );
+        }
+
+        if let Value::Object(ref mut map) = doc {
+            map.insert("b".to_owned(), Value::Bool(true));
+        }
+
+        save_doc(
+            &file_name,
+            &doc,
+            persisted.format,
+            persisted.compression,
+            persisted.source.as_ref(),
+            &persisted.datetimes,
+            Some(persisted.fingerprint),
+            false,
+        )?;
+
+        let contents = Compression::Gzip.decompress(&fs::read(&path)?)?;
+        if contents.as_slice() != b"a: 1 # kept\nb: true\n".as_slice() {
+            bail!(
+                "Expected {:?}, got {:?}",
+                "a: 1 # kept\nb: true\n",
+                String::from_utf8(contents)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_fragment() -> anyhow::Result<()> {
+        let path = scratch_file("spec.yaml", "a: 1\n")?;
+        let file_name = path.to_string_lossy().into_owned();
+        let odd = scratch_file("odd#name.yaml", "a: 1\n")?;
+        let odd_name = odd.to_string_lossy().into_owned();
+
+        let with_pointer = format!("{file_name}#/components/schemas");
+        let cases = [
+            (file_name.as_str(), (file_name.as_str(), None)),
+            (
+                with_pointer.as_str(),
+                (file_name.as_str(), Some("#/components/schemas")),
+            ),
+            (odd_name.as_str(), (odd_name.as_str(), None)),
+            ("-#/items", ("-", Some("#/items"))),
+            ("missing.yaml#/a", ("missing.yaml#/a", None)),
+        ];
+
+        for (argument, expected) in cases {
+            let split = split_fragment(argument);
+            if split != expected {
+                bail!("Expected {:?}, got {:?}", expected, split);
+            }
+        }
+
+        Ok(())
     }
 }
@@ -2,6 +2,9 @@
 
 //!
 
+use core::cmp::Ordering;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
 use serde::{
     de::Visitor,
     ser::{SerializeMap, SerializeSeq},
@@ -28,6 +31,79 @@ pub enum Value {
 
     ///
     Object(indexmap::IndexMap<String, Value>),
+
+    /// Byte string from a binary format, written as base64 in text formats.
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    /// Name of the kind of value, as used in JSON Schema.
+    pub const fn type_name(&self) -> &'static str {
+        match self {
+            &Self::Null => "null",
+            &Self::Bool(_) => "boolean",
+            &Self::String(_) => "string",
+            &Self::Number(_) => "number",
+            &Self::Array(_) => "array",
+            &Self::Object(_) => "object",
+            &Self::Bytes(_) => "bytes",
+        }
+    }
+
+    /// Whether the value has nothing below it, a scalar or an empty collection.
+    pub fn is_leaf(&self) -> bool {
+        match self {
+            &Self::Array(ref array) => array.is_empty(),
+            &Self::Object(ref map) => map.is_empty(),
+            &Self::Null
+            | &Self::Bool(_)
+            | &Self::String(_)
+            | &Self::Number(_)
+            | &Self::Bytes(_) => true,
+        }
+    }
+
+    /// Copy with the keys of an object, or the items of an array, in order. Arrays can only
+    /// be sorted when they hold strings, numbers or booleans, all of the same kind.
+    pub fn sorted(&self) -> Option<Self> {
+        match self {
+            &Self::Object(ref map) => {
+                let mut map = map.clone();
+                map.sort_keys();
+                Some(Self::Object(map))
+            }
+            &Self::Array(ref array) => {
+                // Sorting needs a total order, so every item is checked before
+                let comparable = array.first().is_none_or(|first| {
+                    array
+                        .iter()
+                        .all(|item| compare_scalars(first, item).is_some())
+                });
+                if !comparable {
+                    return None;
+                }
+
+                let mut array = array.clone();
+                array.sort_by(|a, b| compare_scalars(a, b).unwrap_or(Ordering::Equal));
+                Some(Self::Array(array))
+            }
+            &Self::Null
+            | &Self::Bool(_)
+            | &Self::String(_)
+            | &Self::Number(_)
+            | &Self::Bytes(_) => None,
+        }
+    }
+}
+
+/// Order of two scalars of the same kind, `None` when they can't be compared.
+fn compare_scalars(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (&Value::String(ref a), &Value::String(ref b)) => Some(a.cmp(b)),
+        (&Value::Number(ref a), &Value::Number(ref b)) => Some(a.as_f64()?.total_cmp(&b.as_f64()?)),
+        (&Value::Bool(a), &Value::Bool(b)) => Some(a.cmp(&b)),
+        _ => None,
+    }
 }
 
 ///
@@ -85,6 +161,20 @@ impl<'de> Visitor<'de> for ValueVisitor {
         Ok(Value::String(v))
     }
 
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Value::Bytes(v))
+    }
+
     fn visit_none<E>(self) -> Result<Self::Value, E>
     where
         E: serde::de::Error,
@@ -144,6 +234,13 @@ impl Serialize for Value {
             Value::Bool(v) => serializer.serialize_bool(v),
             Value::Number(ref v) => v.serialize(serializer),
             Value::String(ref v) => serializer.serialize_str(v),
+            Value::Bytes(ref v) => {
+                if serializer.is_human_readable() {
+                    serializer.serialize_str(&STANDARD.encode(v))
+                } else {
+                    serializer.serialize_bytes(v)
+                }
+            }
             Value::Array(ref v) => {
                 let mut seq = serializer.serialize_seq(Some(v.len()))?;
                 for item in v {